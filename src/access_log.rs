@@ -0,0 +1,291 @@
+//! Access log (`AppConfig::access_log`): one record per request, separate from the application
+//! logs.
+//!
+//! [`record`] writes each record when the response head is ready, in one of three formats:
+//! - `common`: `host - user [time] "request line" status bytes`, the Common Log Format, with the
+//!   identity id (when logged in) as the user;
+//! - `combined`: `common` followed by the quoted `Referer` and `User-Agent` headers;
+//! - `json`: `timestamp`, `request_id`, `remote_addr`, `method`, `path`, `query`, `protocol`,
+//!   `route` (the matched pattern, e.g. `/admin/sessions/{session_hash}`), `status`, `bytes`,
+//!   `duration_ms` (until the response head), `user`, `inertia` (`xhr` for an Inertia visit,
+//!   `full` for a page load answered with an Inertia page), `referer` and `user_agent`.
+//!
+//! Records go to stdout or to `access_log.file`, rotated per `access_log.rotation`, through a
+//! background writer set up by [`init`] and flushed by [`shutdown`].
+//!
+//! Records only ever contain the fields above: no cookies, `Authorization` or other headers, and
+//! no request or response bodies (form fields, `/echo` payloads, ...). Query parameters whose name
+//! contains `password`, in the request line and in the `Referer`, are logged as `<redacted>`.
+//! `bytes` is the response body size when it is known up front (`-` or `null` for streams).
+//! The host (`remote_addr`) and `User-Agent` come from `client.rs`: the forwarded client IP only
+//! behind `trusted_proxies`, and the user agent truncated.
+
+use std::{
+    io::Write as _,
+    path::Path,
+    sync::{Mutex, OnceLock},
+    time::Instant,
+};
+
+use actix_identity::{Identity, IdentityExt as _};
+use actix_web::{
+    Error, FromRequest as _, HttpMessage as _,
+    body::{BodySize, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header,
+    middleware::Next,
+    web,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+use tracing_appender::{
+    non_blocking::{NonBlocking, NonBlockingBuilder, WorkerGuard},
+    rolling::{RollingFileAppender, Rotation},
+};
+
+use crate::{
+    client,
+    config::{AccessLogConfig, AccessLogFormat, AccessLogRotation, ConfigHandle},
+    telemetry::RequestId,
+};
+
+/// Request header marking an Inertia visit, and response header marking an Inertia page.
+const X_INERTIA: &str = "x-inertia";
+
+/// Replaces the value of sensitive query parameters.
+const REDACTED: &str = "<redacted>";
+
+/// Where records are written; set by [`init`].
+static WRITER: OnceLock<NonBlocking> = OnceLock::new();
+
+/// Flushes the background writer when dropped by [`shutdown`].
+static GUARD: Mutex<Option<WorkerGuard>> = Mutex::new(None);
+
+/// Open the output for `cfg`: `cfg.file` if set (creating its directory), stdout otherwise.
+/// Call once at startup; the output does not follow reloads.
+pub fn init(cfg: &AccessLogConfig) -> std::io::Result<()> {
+    let builder = NonBlockingBuilder::default().lossy(false).thread_name("access-log");
+    let (writer, guard) = match &cfg.file {
+        Some(file) => builder.finish(appender(file, cfg)?),
+        None => builder.finish(std::io::stdout()),
+    };
+    let _ = WRITER.set(writer);
+    *GUARD.lock().unwrap_or_else(|e| e.into_inner()) = Some(guard);
+    Ok(())
+}
+
+/// Write out the records still queued. Later records are dropped.
+pub fn shutdown() {
+    drop(GUARD.lock().unwrap_or_else(|e| e.into_inner()).take());
+}
+
+fn appender(file: &Path, cfg: &AccessLogConfig) -> std::io::Result<RollingFileAppender> {
+    let rotation = match cfg.rotation {
+        AccessLogRotation::Hourly => Rotation::HOURLY,
+        AccessLogRotation::Daily => Rotation::DAILY,
+        AccessLogRotation::Weekly => Rotation::WEEKLY,
+        AccessLogRotation::Never => Rotation::NEVER,
+    };
+    let mut builder = RollingFileAppender::builder().rotation(rotation);
+    match (file.file_stem(), file.extension()) {
+        (Some(stem), Some(extension)) => {
+            builder = builder
+                .filename_prefix(stem.to_string_lossy())
+                .filename_suffix(extension.to_string_lossy());
+        }
+        _ => {
+            let name = file.file_name().unwrap_or_default();
+            builder = builder.filename_prefix(name.to_string_lossy());
+        }
+    }
+    if cfg.max_files > 0 {
+        builder = builder.max_log_files(cfg.max_files);
+    }
+    let directory =
+        file.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    builder.build(directory).map_err(|e| {
+        std::io::Error::other(format!("cannot open access log {}: {e}", file.display()))
+    })
+}
+
+/// Identity id of the request, in the request extensions.
+struct User(String);
+
+/// Middleware noting the identity for the access log: the one after the handler ran (so a login
+/// is logged with its user), or before it (so is a logout). Must be wrapped inside
+/// `session::enforce_registry` (i.e. registered before it with `.wrap`), so revoked sessions are
+/// anonymous.
+pub async fn identify(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let before = req.get_identity().ok().and_then(|identity| identity.id().ok());
+    let res = next.call(req).await?;
+    let after = Identity::extract(res.request()).into_inner().ok().and_then(|i| i.id().ok());
+    if let Some(user) = after.or(before) {
+        res.request().extensions_mut().insert(User(user));
+    }
+    Ok(res)
+}
+
+/// Middleware writing the access log record of each request, including requests that fail in an
+/// inner middleware (e.g. `413` from `limits::apply`). Wrap it inside `telemetry::request_span`,
+/// so records carry the request ID.
+pub async fn record(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let format = req
+        .app_data::<web::Data<ConfigHandle>>()
+        .map(|handle| handle.load())
+        .filter(|cfg| cfg.access_log.enabled)
+        .map(|cfg| cfg.access_log.format);
+    let (Some(format), Some(writer)) = (format, WRITER.get()) else {
+        return next.call(req).await;
+    };
+
+    let started = Instant::now();
+    let timestamp = Utc::now();
+    let headers = req.headers();
+    let header = |name: header::HeaderName| {
+        headers.get(name).map(|v| String::from_utf8_lossy(v.as_bytes()).into_owned())
+    };
+    // Before borrowing the extensions, which `client::ip` caches the connection info in.
+    let remote_addr = client::ip(req.request());
+    let mut record = Record {
+        timestamp,
+        request_id: req.extensions().get::<RequestId>().map(|id| id.0.clone()),
+        remote_addr,
+        method: req.method().to_string(),
+        path: req.path().to_owned(),
+        query: Some(redact_query(req.query_string())).filter(|q| !q.is_empty()),
+        protocol: format!("{:?}", req.version()),
+        route: None,
+        status: 0,
+        bytes: None,
+        duration_ms: 0.0,
+        user: None,
+        inertia: headers.contains_key(X_INERTIA).then_some("xhr"),
+        referer: header(header::REFERER).map(|referer| redact_url(&referer)),
+        user_agent: client::user_agent(req.request()),
+    };
+
+    let res = next.call(req).await;
+    record.duration_ms = started.elapsed().as_micros() as f64 / 1000.0;
+    match &res {
+        Ok(res) => {
+            let request = res.request();
+            record.route = request.match_pattern().filter(|route| !route.is_empty());
+            record.user = request.extensions().get::<User>().map(|user| user.0.clone());
+            record.status = res.status().as_u16();
+            record.bytes = match res.response().body().size() {
+                BodySize::None => Some(0),
+                BodySize::Sized(size) => Some(size),
+                BodySize::Stream => None,
+            };
+            if record.inertia.is_none() && res.headers().contains_key(X_INERTIA) {
+                record.inertia = Some("full");
+            }
+        }
+        Err(e) => record.status = e.as_response_error().status_code().as_u16(),
+    }
+
+    let mut line = match format {
+        AccessLogFormat::Common => record.common(),
+        AccessLogFormat::Combined => record.combined(),
+        AccessLogFormat::Json => serde_json::to_string(&record).unwrap_or_default(),
+    };
+    line.push('\n');
+    // Fails only after `shutdown`.
+    let _ = writer.clone().write_all(line.as_bytes());
+    res
+}
+
+/// One access log record; serialized as is for the `json` format.
+#[derive(Serialize)]
+struct Record {
+    #[serde(serialize_with = "rfc3339")]
+    timestamp: DateTime<Utc>,
+    request_id: Option<String>,
+    remote_addr: Option<String>,
+    method: String,
+    path: String,
+    query: Option<String>,
+    protocol: String,
+    route: Option<String>,
+    status: u16,
+    bytes: Option<u64>,
+    duration_ms: f64,
+    user: Option<String>,
+    inertia: Option<&'static str>,
+    referer: Option<String>,
+    user_agent: Option<String>,
+}
+
+impl Record {
+    fn common(&self) -> String {
+        let target = match &self.query {
+            Some(query) => format!("{}?{query}", self.path),
+            None => self.path.clone(),
+        };
+        format!(
+            "{} - {} [{}] \"{} {} {}\" {} {}",
+            self.remote_addr.as_deref().unwrap_or("-"),
+            self.user.as_deref().map(escape).unwrap_or_else(|| "-".to_owned()),
+            self.timestamp.format("%d/%b/%Y:%H:%M:%S %z"),
+            self.method,
+            escape(&target),
+            self.protocol,
+            self.status,
+            self.bytes.filter(|&bytes| bytes > 0).map_or("-".to_owned(), |b| b.to_string()),
+        )
+    }
+
+    fn combined(&self) -> String {
+        let quoted = |value: &Option<String>| value.as_deref().map_or("-".to_owned(), escape);
+        format!("{} \"{}\" \"{}\"", self.common(), quoted(&self.referer), quoted(&self.user_agent))
+    }
+}
+
+fn rfc3339<S: serde::Serializer>(time: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&time.to_rfc3339_opts(SecondsFormat::Millis, true))
+}
+
+/// `query` with the value of every parameter whose name contains `password` replaced.
+fn redact_query(query: &str) -> String {
+    query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((name, _)) if name.to_ascii_lowercase().contains("password") => {
+                format!("{name}={REDACTED}")
+            }
+            _ => pair.to_owned(),
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// `url` with its query redacted like the request's (see [`redact_query`]).
+fn redact_url(url: &str) -> String {
+    match url.split_once('?') {
+        Some((base, query)) => format!("{base}?{}", redact_query(query)),
+        None => url.to_owned(),
+    }
+}
+
+/// `value` for a quoted field of the text formats: quotes, backslashes and control characters
+/// escaped, so a client cannot forge fields or lines.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c if c.is_control() => escaped.push_str(&format!("\\x{:02x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,97 @@
+use std::{
+    collections::VecDeque,
+    fs::{File, OpenOptions},
+    io::{BufRead as _, BufReader, Write as _},
+    os::unix::fs::OpenOptionsExt as _,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use futures_util::future::BoxFuture;
+
+use super::{AuditEvent, AuditQuery, AuditSink};
+
+/// Events as JSON lines in a file opened in append mode, created with mode `0600`. Lines are
+/// never rewritten; to start a new file, rename the old one and restart.
+#[derive(Clone)]
+pub struct FileAuditSink {
+    path: PathBuf,
+    file: Arc<Mutex<File>>,
+}
+
+impl FileAuditSink {
+    pub fn open(path: PathBuf) -> anyhow::Result<Self> {
+        let file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .mode(0o600)
+            .open(&path)
+            .map_err(|e| anyhow::anyhow!("failed to open {}: {e}", path.display()))?;
+        Ok(FileAuditSink { path, file: Arc::new(Mutex::new(file)) })
+    }
+}
+
+impl AuditSink for FileAuditSink {
+    fn record<'a>(&'a self, event: &'a AuditEvent) -> BoxFuture<'a, anyhow::Result<()>> {
+        let file = Arc::clone(&self.file);
+        Box::pin(async move {
+            let mut line = serde_json::to_vec(event)?;
+            line.push(b'\n');
+            actix_web::web::block(move || {
+                let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+                file.write_all(&line).map_err(anyhow::Error::from)
+            })
+            .await?
+        })
+    }
+
+    /// Scans the whole file; lines that are not events are skipped.
+    fn query<'a>(
+        &'a self,
+        query: &'a AuditQuery,
+    ) -> BoxFuture<'a, anyhow::Result<Vec<AuditEvent>>> {
+        let path = self.path.clone();
+        let query = query.clone();
+        Box::pin(async move {
+            actix_web::web::block(move || {
+                let reader = BufReader::new(File::open(&path)?);
+                let limit = query.limit();
+                let mut newest = VecDeque::with_capacity(limit);
+                for line in reader.lines() {
+                    let Ok(event) = serde_json::from_str::<AuditEvent>(&line?) else {
+                        continue;
+                    };
+                    if query.matches(&event) {
+                        if newest.len() == limit {
+                            newest.pop_front();
+                        }
+                        newest.push_back(event);
+                    }
+                }
+                Ok(newest.into_iter().rev().collect())
+            })
+            .await?
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn records_and_queries_events() {
+        let path = std::env::temp_dir().join(format!("audit-file-{}.ndjson", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let sink = FileAuditSink::open(path.clone()).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        // A line that is no event, e.g. cut short by a crash, is skipped.
+        std::fs::write(&path, "{\"timestamp\":\n").unwrap();
+
+        crate::audit::check_queries(&sink).await;
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,291 @@
+/*! Security audit log: authentication and session lifecycle events.
+
+Every event is an [`AuditEvent`] written to the [`AuditSink`] selected by `audit.sink`:
+- `stdout` -> [`StdoutAuditSink`], one JSON object per line;
+- `file`   -> [`FileAuditSink`], JSON lines appended to `audit.path`;
+- `sqlite` -> [`SqliteAuditSink`], rows of an append-only table in `audit.path`.
+
+Recorded actions ([`AuditAction`]):
+- `login_success` and `login_failure` (`handlers::login`);
+- `logout` (`handlers::logout`);
+- `session_expired`: a request presented a session that is no longer registered, because its TTL
+  ran out or it was revoked earlier (`session::enforce_registry`);
+- `session_revoked`: an admin revoked one session, or every session of a user (`handlers::admin`);
+- `privilege_changed`: a configuration reload set, replaced or removed `admin_token` (`reload`);
+- `password_changed`: nothing in the app changes passwords yet; a user store that gains that
+  ability records it under this action.
+
+Events carry the user concerned, the `actor` of admin actions, a SHA-256 hash of the session ID
+(never the ID itself), and the client IP, user agent and request ID when a request caused them
+(see `client.rs`: forwarded IPs count only from `trusted_proxies`, user agents are truncated).
+Passwords are never part of an event. [`record`] does not fail the request: a sink error is
+logged instead.
+
+`GET /admin/audit` (`handlers::admin::audit_log`) reads events back from the `file` and `sqlite`
+sinks.
+*/
+
+use std::sync::Arc;
+
+use actix_web::{HttpMessage as _, HttpRequest, web};
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest as _, Sha256};
+use tracing::error;
+
+use crate::client;
+use crate::config::{AuditConfig, AuditSinkKind};
+use crate::telemetry::RequestId;
+
+pub mod file;
+pub mod sqlite;
+pub mod stdout;
+
+pub use file::FileAuditSink;
+pub use sqlite::SqliteAuditSink;
+pub use stdout::StdoutAuditSink;
+
+/// Most events one query returns.
+pub const MAX_QUERY_LIMIT: usize = 1000;
+
+/// What happened.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    LoginSuccess,
+    LoginFailure,
+    Logout,
+    SessionExpired,
+    SessionRevoked,
+    PasswordChanged,
+    PrivilegeChanged,
+}
+
+impl AuditAction {
+    const ALL: [AuditAction; 7] = [
+        AuditAction::LoginSuccess,
+        AuditAction::LoginFailure,
+        AuditAction::Logout,
+        AuditAction::SessionExpired,
+        AuditAction::SessionRevoked,
+        AuditAction::PasswordChanged,
+        AuditAction::PrivilegeChanged,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AuditAction::LoginSuccess => "login_success",
+            AuditAction::LoginFailure => "login_failure",
+            AuditAction::Logout => "logout",
+            AuditAction::SessionExpired => "session_expired",
+            AuditAction::SessionRevoked => "session_revoked",
+            AuditAction::PasswordChanged => "password_changed",
+            AuditAction::PrivilegeChanged => "privilege_changed",
+        }
+    }
+
+    /// The action named `name` (as in [`AuditAction::as_str`]).
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|action| action.as_str() == name)
+    }
+}
+
+/// One audit log entry.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuditEvent {
+    /// UTC, RFC 3339 with milliseconds (`2026-01-31T12:00:00.000Z`), so it also sorts as text.
+    pub timestamp: String,
+    pub action: AuditAction,
+    /// The user the event is about: who logged in or out, whose session ended, ...
+    pub user: Option<String>,
+    /// Who performed an admin action: `admin_token`.
+    pub actor: Option<String>,
+    /// Hash of the session ID (see [`session_hash`]).
+    pub session: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub request_id: Option<String>,
+    /// Free-form context, e.g. how many sessions a revocation ended.
+    pub detail: Option<String>,
+}
+
+impl AuditEvent {
+    /// An event happening now, outside any request.
+    pub fn new(action: AuditAction) -> Self {
+        AuditEvent {
+            timestamp: timestamp(Utc::now()),
+            action,
+            user: None,
+            actor: None,
+            session: None,
+            ip: None,
+            user_agent: None,
+            request_id: None,
+            detail: None,
+        }
+    }
+
+    /// An event caused by `req`, with its client IP, user agent and request ID.
+    pub fn from_request(action: AuditAction, req: &HttpRequest) -> Self {
+        AuditEvent {
+            ip: client::ip(req),
+            user_agent: client::user_agent(req),
+            request_id: req.extensions().get::<RequestId>().map(|id| id.0.clone()),
+            ..Self::new(action)
+        }
+    }
+
+    pub fn user(mut self, user: impl Into<String>) -> Self {
+        self.user = Some(user.into());
+        self
+    }
+
+    pub fn actor(mut self, actor: impl Into<String>) -> Self {
+        self.actor = Some(actor.into());
+        self
+    }
+
+    /// Record the hash of `session_id`.
+    pub fn session(mut self, session_id: &str) -> Self {
+        self.session = Some(session_hash(session_id));
+        self
+    }
+
+    pub fn detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+}
+
+/// `time` in the format of [`AuditEvent::timestamp`].
+pub fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// First 128 bits of the SHA-256 of `session_id`, in hex: enough to tell sessions apart and to
+/// find a known session's events, useless for taking the session over.
+pub fn session_hash(session_id: &str) -> String {
+    Sha256::digest(session_id.as_bytes())[..16].iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Filter for [`AuditSink::query`], from the `GET /admin/audit` query string.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct AuditQuery {
+    pub user: Option<String>,
+    pub action: Option<AuditAction>,
+    /// Earliest timestamp, inclusive, in the format of [`AuditEvent::timestamp`].
+    pub since: Option<String>,
+    /// Latest timestamp, exclusive, in the format of [`AuditEvent::timestamp`].
+    pub until: Option<String>,
+    /// Most events returned, up to [`MAX_QUERY_LIMIT`]. Default: 100
+    pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+    /// `limit` within 1..=[`MAX_QUERY_LIMIT`].
+    pub fn limit(&self) -> usize {
+        self.limit.unwrap_or(100).clamp(1, MAX_QUERY_LIMIT)
+    }
+
+    /// Whether `event` passes every filter.
+    pub fn matches(&self, event: &AuditEvent) -> bool {
+        self.user.as_ref().is_none_or(|user| event.user.as_ref() == Some(user))
+            && self.action.is_none_or(|action| event.action == action)
+            && self.since.as_ref().is_none_or(|since| &event.timestamp >= since)
+            && self.until.as_ref().is_none_or(|until| &event.timestamp < until)
+    }
+}
+
+/// Destination of audit events.
+pub trait AuditSink: Send + Sync {
+    /// Append `event`.
+    fn record<'a>(&'a self, event: &'a AuditEvent) -> BoxFuture<'a, anyhow::Result<()>>;
+
+    /// Events matching `query`, newest first, at most `query.limit()` of them. Sinks that cannot
+    /// read their events back fail.
+    fn query<'a>(
+        &'a self,
+        _query: &'a AuditQuery,
+    ) -> BoxFuture<'a, anyhow::Result<Vec<AuditEvent>>> {
+        Box::pin(async { Err(anyhow::anyhow!("this audit sink cannot be queried")) })
+    }
+}
+
+/// Build the sink selected by `cfg.sink`.
+pub fn from_config(cfg: &AuditConfig) -> anyhow::Result<Arc<dyn AuditSink>> {
+    let path = || {
+        cfg.path
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("audit.path is required for audit.sink = {}", cfg.sink))
+    };
+
+    Ok(match cfg.sink {
+        AuditSinkKind::Stdout => Arc::new(StdoutAuditSink),
+        AuditSinkKind::File => Arc::new(FileAuditSink::open(path()?)?),
+        AuditSinkKind::Sqlite => Arc::new(SqliteAuditSink::open(path()?)?),
+    })
+}
+
+/// Write `event` to `sink`, logging a failure.
+pub async fn write(sink: &dyn AuditSink, event: AuditEvent) {
+    if let Err(e) = sink.record(&event).await {
+        error!(action = event.action.as_str(), "Cannot write audit event: {e}");
+    }
+}
+
+/// Write `event` to the app's sink, if any.
+pub async fn record(req: &HttpRequest, event: AuditEvent) {
+    if let Some(sink) = req.app_data::<web::Data<dyn AuditSink>>() {
+        write(sink.get_ref(), event).await;
+    }
+}
+
+/// Record a fixed history into `sink`, which must be empty, and check what
+/// [`AuditSink::query`] returns for each filter. Shared by the tests of the queryable sinks.
+#[cfg(test)]
+pub(crate) async fn check_queries(sink: &dyn AuditSink) {
+    let at = |minute: u32| format!("2026-01-31T12:{minute:02}:00.000Z");
+    let event = |minute, action, user: &str| AuditEvent {
+        timestamp: at(minute),
+        ..AuditEvent::new(action).user(user)
+    };
+    let mut login = event(1, AuditAction::LoginSuccess, "alice").session("s1");
+    login.ip = Some("203.0.113.9".to_owned());
+    login.user_agent = Some("curl/8.0".to_owned());
+    login.request_id = Some("r1".to_owned());
+    let history = [
+        event(0, AuditAction::LoginFailure, "alice"),
+        login,
+        event(2, AuditAction::LoginSuccess, "bob"),
+        event(3, AuditAction::Logout, "alice"),
+        event(4, AuditAction::SessionRevoked, "bob").actor("admin_token").detail("1 sessions"),
+    ];
+    for event in &history {
+        sink.record(event).await.unwrap();
+    }
+
+    let all = sink.query(&AuditQuery::default()).await.unwrap();
+    assert_eq!(all, history.iter().rev().cloned().collect::<Vec<_>>(), "newest first, intact");
+
+    let cases = [
+        (AuditQuery { user: Some("alice".to_owned()), ..Default::default() }, vec![3, 1, 0]),
+        (AuditQuery { action: Some(AuditAction::LoginSuccess), ..Default::default() }, vec![2, 1]),
+        (AuditQuery { since: Some(at(1)), ..Default::default() }, vec![4, 3, 2, 1]),
+        (AuditQuery { until: Some(at(3)), ..Default::default() }, vec![2, 1, 0]),
+        (AuditQuery { since: Some(at(1)), until: Some(at(3)), ..Default::default() }, vec![2, 1]),
+        (AuditQuery { since: Some(at(5)), ..Default::default() }, vec![]),
+        (AuditQuery { limit: Some(2), ..Default::default() }, vec![4, 3]),
+        (
+            AuditQuery { user: Some("alice".to_owned()), limit: Some(2), ..Default::default() },
+            vec![3, 1],
+        ),
+        (AuditQuery { limit: Some(0), ..Default::default() }, vec![4]),
+    ];
+    for (query, minutes) in cases {
+        let events = sink.query(&query).await.unwrap();
+        let timestamps: Vec<_> = events.into_iter().map(|event| event.timestamp).collect();
+        let expected: Vec<_> = minutes.into_iter().map(at).collect();
+        assert_eq!(timestamps, expected, "{query:?}");
+    }
+}
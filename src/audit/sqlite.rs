@@ -0,0 +1,164 @@
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use futures_util::future::BoxFuture;
+use rusqlite::{Connection, params, types::Type};
+
+use super::{AuditAction, AuditEvent, AuditQuery, AuditSink};
+
+/// Events in a SQLite database. The table is created on first open, with triggers that reject
+/// updates and deletes:
+///
+/// ```sql
+/// CREATE TABLE audit_events (
+///     id         INTEGER PRIMARY KEY,
+///     timestamp  TEXT NOT NULL,
+///     action     TEXT NOT NULL,
+///     user       TEXT,
+///     actor      TEXT,
+///     session    TEXT,
+///     ip         TEXT,
+///     user_agent TEXT,
+///     request_id TEXT,
+///     detail     TEXT
+/// );
+/// ```
+#[derive(Clone)]
+pub struct SqliteAuditSink {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteAuditSink {
+    pub fn open(path: PathBuf) -> anyhow::Result<Self> {
+        let conn = Connection::open(&path)
+            .map_err(|e| anyhow::anyhow!("failed to open {}: {e}", path.display()))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS audit_events (
+                id         INTEGER PRIMARY KEY,
+                timestamp  TEXT NOT NULL,
+                action     TEXT NOT NULL,
+                user       TEXT,
+                actor      TEXT,
+                session    TEXT,
+                ip         TEXT,
+                user_agent TEXT,
+                request_id TEXT,
+                detail     TEXT
+            );
+            CREATE INDEX IF NOT EXISTS audit_events_user ON audit_events (user, id);
+            CREATE TRIGGER IF NOT EXISTS audit_events_no_update BEFORE UPDATE ON audit_events
+            BEGIN SELECT RAISE(ABORT, 'audit events are append-only'); END;
+            CREATE TRIGGER IF NOT EXISTS audit_events_no_delete BEFORE DELETE ON audit_events
+            BEGIN SELECT RAISE(ABORT, 'audit events are append-only'); END;",
+        )?;
+        Ok(SqliteAuditSink { conn: Arc::new(Mutex::new(conn)) })
+    }
+}
+
+impl AuditSink for SqliteAuditSink {
+    fn record<'a>(&'a self, event: &'a AuditEvent) -> BoxFuture<'a, anyhow::Result<()>> {
+        let conn = Arc::clone(&self.conn);
+        let event = event.clone();
+        Box::pin(async move {
+            actix_web::web::block(move || {
+                let conn = conn.lock().unwrap_or_else(|e| e.into_inner());
+                conn.execute(
+                    "INSERT INTO audit_events
+                     (timestamp, action, user, actor, session, ip, user_agent, request_id, detail)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                    params![
+                        event.timestamp,
+                        event.action.as_str(),
+                        event.user,
+                        event.actor,
+                        event.session,
+                        event.ip,
+                        event.user_agent,
+                        event.request_id,
+                        event.detail,
+                    ],
+                )?;
+                Ok(())
+            })
+            .await?
+        })
+    }
+
+    fn query<'a>(
+        &'a self,
+        query: &'a AuditQuery,
+    ) -> BoxFuture<'a, anyhow::Result<Vec<AuditEvent>>> {
+        let conn = Arc::clone(&self.conn);
+        let query = query.clone();
+        Box::pin(async move {
+            actix_web::web::block(move || {
+                let conn = conn.lock().unwrap_or_else(|e| e.into_inner());
+                let mut statement = conn.prepare(
+                    "SELECT timestamp, action, user, actor, session, ip, user_agent, request_id,
+                            detail
+                     FROM audit_events
+                     WHERE (?1 IS NULL OR user = ?1)
+                       AND (?2 IS NULL OR action = ?2)
+                       AND (?3 IS NULL OR timestamp >= ?3)
+                       AND (?4 IS NULL OR timestamp < ?4)
+                     ORDER BY id DESC
+                     LIMIT ?5",
+                )?;
+                let rows = statement.query_map(
+                    params![
+                        query.user,
+                        query.action.map(AuditAction::as_str),
+                        query.since,
+                        query.until,
+                        query.limit() as i64,
+                    ],
+                    |row| {
+                        let action: String = row.get(1)?;
+                        let action = AuditAction::parse(&action).ok_or_else(|| {
+                            let e = anyhow::anyhow!("unknown audit action {action:?}");
+                            rusqlite::Error::FromSqlConversionFailure(1, Type::Text, e.into())
+                        })?;
+                        Ok(AuditEvent {
+                            timestamp: row.get(0)?,
+                            action,
+                            user: row.get(2)?,
+                            actor: row.get(3)?,
+                            session: row.get(4)?,
+                            ip: row.get(5)?,
+                            user_agent: row.get(6)?,
+                            request_id: row.get(7)?,
+                            detail: row.get(8)?,
+                        })
+                    },
+                )?;
+                let events = rows.collect::<Result<Vec<_>, _>>()?;
+                Ok(events)
+            })
+            .await?
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn records_and_queries_events() {
+        let path = std::env::temp_dir().join(format!("audit-sqlite-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let sink = SqliteAuditSink::open(path.clone()).unwrap();
+        crate::audit::check_queries(&sink).await;
+
+        let reopened = SqliteAuditSink::open(path.clone()).unwrap();
+        let events = reopened.query(&AuditQuery::default()).await.unwrap();
+        assert_eq!(events.len(), 5, "events survive a reopen");
+        let conn = reopened.conn.lock().unwrap();
+        assert!(conn.execute("DELETE FROM audit_events", []).is_err(), "append-only");
+        assert!(conn.execute("UPDATE audit_events SET user = 'mallory'", []).is_err());
+        drop(conn);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,21 @@
+use std::io::Write as _;
+
+use futures_util::future::BoxFuture;
+
+use super::{AuditEvent, AuditSink};
+
+/// Events as JSON lines on stdout, next to the logs; tell them apart by the `action` key. Cannot
+/// be queried.
+#[derive(Clone, Copy, Default)]
+pub struct StdoutAuditSink;
+
+impl AuditSink for StdoutAuditSink {
+    fn record<'a>(&'a self, event: &'a AuditEvent) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let mut line = serde_json::to_vec(event)?;
+            line.push(b'\n');
+            std::io::stdout().lock().write_all(&line)?;
+            Ok(())
+        })
+    }
+}
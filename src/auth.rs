@@ -0,0 +1,108 @@
+//! The authenticated user as seen by handlers and pages.
+//!
+//! Login stores an [`AuthUser`] snapshot (id, display name, roles, session metadata) in the
+//! session next to the identity. [`AuthUser`] is then available as an extractor
+//! (`AuthUser` rejects anonymous requests with 401, `Option<AuthUser>` does not), and
+//! [`shared_auth`] feeds the global `auth` Inertia prop so pages no longer build it themselves.
+
+use std::future::{Ready, ready};
+
+use actix_identity::IdentityExt;
+use actix_session::{Session, SessionExt, SessionInsertError};
+use actix_web::{FromRequest, HttpRequest, dev::Payload, error::ErrorUnauthorized};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::users::UserRecord;
+
+const AUTH_USER_KEY: &str = "auth_user";
+
+/// Metadata about the current login session.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionInfo {
+    /// Unix timestamp (seconds) of the login.
+    pub logged_in_at: i64,
+    /// Unix timestamp (seconds) after which the session is no longer valid, unless it is written
+    /// (and so extended) again before then.
+    pub expires_at: i64,
+}
+
+/// The logged-in user.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthUser {
+    pub id: String,
+    pub name: String,
+    pub roles: Vec<String>,
+    pub session: SessionInfo,
+}
+
+impl AuthUser {
+    /// Snapshot `user` at login time; the session lasts `ttl_seconds`.
+    pub fn new(user: &UserRecord, ttl_seconds: u64) -> Self {
+        let now = Utc::now().timestamp();
+        AuthUser {
+            id: user.username.clone(),
+            name: user.name().to_owned(),
+            roles: user.roles.clone(),
+            session: SessionInfo { logged_in_at: now, expires_at: expiry(now, ttl_seconds) },
+        }
+    }
+
+    /// Move the stored snapshot's expiry to `ttl_seconds` from now, after the session store
+    /// extended the session.
+    pub fn extend(session: &Session, ttl_seconds: u64) -> Result<(), SessionInsertError> {
+        match session.get::<AuthUser>(AUTH_USER_KEY).ok().flatten() {
+            Some(mut user) => {
+                user.session.expires_at = expiry(Utc::now().timestamp(), ttl_seconds);
+                user.save(session)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Store this snapshot in the session (call right after `Identity::login`).
+    pub fn save(&self, session: &Session) -> Result<(), SessionInsertError> {
+        session.insert(AUTH_USER_KEY, self)
+    }
+
+    /// Resolve the current user from the request's identity and session.
+    ///
+    /// Returns `None` for anonymous requests. If the session lacks a snapshot for this identity
+    /// (e.g. it predates this feature), a minimal one is derived from the identity id.
+    pub fn from_req(req: &HttpRequest) -> Option<Self> {
+        let id = req.get_identity().ok()?.id().ok()?;
+        let stored = req.get_session().get::<AuthUser>(AUTH_USER_KEY).ok().flatten();
+
+        Some(match stored {
+            Some(user) if user.id == id => user,
+            _ => AuthUser {
+                name: id.clone(),
+                id,
+                roles: Vec::new(),
+                session: SessionInfo { logged_in_at: 0, expires_at: 0 },
+            },
+        })
+    }
+}
+
+/// `now` plus `ttl_seconds`, saturating.
+fn expiry(now: i64, ttl_seconds: u64) -> i64 {
+    now.saturating_add(i64::try_from(ttl_seconds).unwrap_or(i64::MAX))
+}
+
+impl FromRequest for AuthUser {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(AuthUser::from_req(req).ok_or_else(|| ErrorUnauthorized("not logged in")))
+    }
+}
+
+/// The `auth` shared prop: `{ "user": AuthUser | null }`.
+pub fn shared_auth(req: &HttpRequest) -> Value {
+    json!({ "user": AuthUser::from_req(req) })
+}
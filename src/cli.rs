@@ -0,0 +1,241 @@
+//! Command-line interface.
+//!
+//! ```text
+//! hello-actix [serve [--port-file FILE]]     run the server (default), writing its TCP
+//!                                            addresses to FILE once it listens
+//! hello-actix check-config                   load and validate the configuration, report problems
+//! hello-actix print-config [--format toml|json]
+//!                                            dump the effective configuration, secrets redacted
+//! hello-actix gen-key                        print a fresh `base64:` secret key
+//! hello-actix hash-password                  print the Argon2id hash of the password on stdin
+//! hello-actix check-trace FILE               replay a `model_trace` file against SessionModel.tla
+//! ```
+//!
+//! Every command except `gen-key`, `hash-password` and `check-trace` loads the configuration
+//! exactly like `serve` does (`.env`, `config/` files, `APP_*` variables, `*_FILE` secrets), so
+//! `check-config` is a dry run of startup. Exit status is 0 on success, 1 on configuration
+//! errors (or a trace that breaks the model) and 2 on usage errors.
+
+use std::{
+    fs::File,
+    io::{BufReader, Read as _, Write as _},
+    path::{Path, PathBuf},
+};
+
+use crate::config::{AppConfig, ConfigProblem, SecretKey, Severity};
+use crate::model_trace;
+use crate::users;
+
+const USAGE: &str = "\
+Usage: hello-actix [COMMAND]
+
+Commands:
+  serve [--port-file FILE]       Run the HTTP server (default); write its bound TCP addresses
+                                 (one host:port per line) to FILE once it listens
+  check-config                   Load and validate the configuration, print every problem
+  print-config [--format FMT]    Print the effective configuration (FMT: toml or json, default toml)
+  gen-key                        Print a new secret key for APP_SECRET_KEY
+  hash-password                  Read a password from stdin and print its Argon2id hash
+  check-trace FILE               Check a trace recorded with APP_MODEL_TRACE against the model
+  help                           Print this message";
+
+/// Output format of `print-config`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    Toml,
+    Json,
+}
+
+/// A parsed command line.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Command {
+    Serve { port_file: Option<PathBuf> },
+    CheckConfig,
+    PrintConfig(Format),
+    GenKey,
+    HashPassword,
+    CheckTrace(PathBuf),
+    Help,
+}
+
+impl Command {
+    /// Parse the arguments after the program name.
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let command = match args.next().as_deref() {
+            None => Command::Serve { port_file: None },
+            Some("serve") => match args.next().as_deref() {
+                None => Command::Serve { port_file: None },
+                Some("--port-file") => match args.next() {
+                    Some(file) => Command::Serve { port_file: Some(file.into()) },
+                    None => return Err("--port-file expects a file".to_owned()),
+                },
+                Some(arg) => return Err(format!("unexpected argument {arg}")),
+            },
+            Some("check-config") => Command::CheckConfig,
+            Some("print-config") => {
+                let format = match (args.next().as_deref(), args.next().as_deref()) {
+                    (None, _) => Format::Toml,
+                    (Some("--format"), Some("toml")) => Format::Toml,
+                    (Some("--format"), Some("json")) => Format::Json,
+                    (Some("--format"), other) => {
+                        return Err(format!(
+                            "--format expects toml or json, got {}",
+                            other.unwrap_or("nothing")
+                        ));
+                    }
+                    (Some(arg), _) => return Err(format!("unexpected argument {arg}")),
+                };
+                Command::PrintConfig(format)
+            }
+            Some("gen-key") => Command::GenKey,
+            Some("hash-password") => Command::HashPassword,
+            Some("check-trace") => match args.next() {
+                Some(file) => Command::CheckTrace(file.into()),
+                None => return Err("check-trace expects a trace file".to_owned()),
+            },
+            Some("help" | "-h" | "--help") => Command::Help,
+            Some(other) => return Err(format!("unknown command {other}")),
+        };
+        match args.next() {
+            Some(arg) => Err(format!("unexpected argument {arg}")),
+            None => Ok(command),
+        }
+    }
+}
+
+/// Print usage after a command-line error and exit with status 2.
+pub fn usage_error(message: &str) -> ! {
+    eprintln!("error: {message}\n\n{USAGE}");
+    std::process::exit(2);
+}
+
+/// `help`: print usage.
+pub fn help() {
+    println!("{USAGE}");
+}
+
+/// Load and validate the configuration for `serve`. Warnings are printed; on any error the full
+/// report is printed and the process exits with status 1.
+pub fn load_or_exit() -> AppConfig {
+    let cfg = load_or_exit_unchecked();
+    let problems = cfg.problems();
+    if problems.iter().any(|p| p.severity == Severity::Error) {
+        print_report(&cfg, &problems);
+        std::process::exit(1);
+    }
+    for warning in &problems {
+        eprintln!("Config warning ({} mode): {warning}", cfg.mode);
+    }
+    cfg
+}
+
+/// `check-config`: print every problem with the source of the offending value. Exits with status 1
+/// if any of them would stop `serve`.
+pub fn check_config() {
+    let cfg = load_or_exit_unchecked();
+    let problems = cfg.problems();
+    print_report(&cfg, &problems);
+    if problems.iter().any(|p| p.severity == Severity::Error) {
+        std::process::exit(1);
+    }
+}
+
+/// `print-config`: dump the effective configuration with secrets redacted.
+pub fn print_config(format: Format) {
+    let cfg = load_or_exit_unchecked();
+    let rendered = match format {
+        Format::Toml => toml::to_string(&cfg).map_err(|e| e.to_string()),
+        Format::Json => serde_json::to_string_pretty(&cfg).map_err(|e| e.to_string()),
+    };
+    match rendered {
+        // Ignore write errors so piping into `head` doesn't panic on a closed pipe.
+        Ok(text) => {
+            let _ = writeln!(std::io::stdout(), "{}", text.trim_end());
+        }
+        Err(e) => {
+            eprintln!("Cannot serialize configuration: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// `gen-key`: print a random key for `APP_SECRET_KEY` / `APP_SECRET_KEYS`.
+pub fn gen_key() {
+    let key = SecretKey::generate();
+    println!("{}", key.encode());
+}
+
+/// `hash-password`: read a password from stdin (one trailing newline is dropped) and print its
+/// Argon2id PHC hash for `password_hash`. Reading stdin keeps the password out of the process list
+/// and shell history.
+pub fn hash_password() {
+    let mut password = String::new();
+    if let Err(e) = std::io::stdin().read_to_string(&mut password) {
+        eprintln!("Cannot read the password from stdin: {e}");
+        std::process::exit(1);
+    }
+    let password = password.strip_suffix('\n').unwrap_or(&password);
+    let password = password.strip_suffix('\r').unwrap_or(password);
+    if password.is_empty() {
+        eprintln!("Empty password");
+        std::process::exit(1);
+    }
+    match users::hash_password(password) {
+        Ok(hash) => println!("{hash}"),
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// `check-trace`: replay a `model_trace` file against `specs/SessionModel.tla`. Exits with status 1
+/// at the first step that breaks the model.
+pub fn check_trace(file: &Path) {
+    let trace = match File::open(file) {
+        Ok(trace) => BufReader::new(trace),
+        Err(e) => {
+            eprintln!("Cannot open {}: {e}", file.display());
+            std::process::exit(1);
+        }
+    };
+    match model_trace::check(trace) {
+        Ok(steps) => println!("Trace OK: {steps} transition(s) follow SessionModel"),
+        Err(violation) => {
+            eprintln!("Trace violates SessionModel at {violation}");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Load the configuration; on a load error (unparsable value, unreadable secret file, ...) print
+/// it and exit with status 1.
+fn load_or_exit_unchecked() -> AppConfig {
+    match AppConfig::load() {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            eprintln!("Configuration error: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Print `problems` grouped by severity, followed by a one-line summary.
+fn print_report(cfg: &AppConfig, problems: &[ConfigProblem]) {
+    let count = |severity| problems.iter().filter(|p| p.severity == severity).count();
+    let (errors, warnings) = (count(Severity::Error), count(Severity::Warning));
+
+    for severity in [Severity::Error, Severity::Warning] {
+        for problem in problems.iter().filter(|p| p.severity == severity) {
+            eprintln!("{severity}: {problem}");
+        }
+    }
+    if errors > 0 {
+        eprintln!(
+            "Configuration invalid ({} mode): {errors} error(s), {warnings} warning(s)",
+            cfg.mode
+        );
+    } else {
+        println!("Configuration OK ({} mode): {warnings} warning(s)", cfg.mode);
+    }
+}
@@ -0,0 +1,174 @@
+//! Client details recorded by the access and audit logs.
+//!
+//! The client IP is the TCP peer, unless that peer is one of `trusted_proxies`: then the chain in
+//! `Forwarded` (or, without it, `X-Forwarded-For`) is walked from the right, past every trusted
+//! proxy, to the first address none of them is. Clients can put anything in those headers, so
+//! entries left of that address, and the headers on direct connections, are ignored. The
+//! `User-Agent` header is capped at [`MAX_USER_AGENT`] characters.
+
+use std::net::{IpAddr, SocketAddr};
+
+use actix_web::{
+    HttpRequest,
+    http::header::{self, HeaderMap},
+    web,
+};
+
+use crate::config::ConfigHandle;
+
+/// Longest `User-Agent` recorded, in characters; longer ones are cut and end with `…`.
+pub const MAX_USER_AGENT: usize = 256;
+
+/// The client IP of `req`, through trusted proxies.
+pub fn ip(req: &HttpRequest) -> Option<String> {
+    let peer = req.peer_addr()?.ip();
+    let trusted = match req.app_data::<web::Data<ConfigHandle>>() {
+        Some(cfg) => cfg.load().trusted_proxies.clone(),
+        None => Vec::new(),
+    };
+    Some(client_ip(peer, req.headers(), &trusted).to_string())
+}
+
+/// Step left through the forwarding chain while the current hop is a trusted proxy, which vouches
+/// for the entry before it. An entry that is no IP (`unknown`, an obfuscated name) ends the walk
+/// at the proxy that wrote it.
+fn client_ip(peer: IpAddr, headers: &HeaderMap, trusted: &[IpAddr]) -> IpAddr {
+    let mut client = peer;
+    for hop in forwarded_chain(headers).iter().rev() {
+        if !trusted.contains(&client) {
+            break;
+        }
+        match parse_ip(hop) {
+            Some(ip) => client = ip,
+            None => break,
+        }
+    }
+    client
+}
+
+/// The `for=` addresses of `Forwarded`, or else the `X-Forwarded-For` list, oldest first, across
+/// repeated headers.
+fn forwarded_chain(headers: &HeaderMap) -> Vec<&str> {
+    let list = |name| {
+        headers
+            .get_all(name)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .collect::<Vec<_>>()
+    };
+    let forwarded = list(header::FORWARDED);
+    if forwarded.is_empty() {
+        return list(header::X_FORWARDED_FOR);
+    }
+    // An element without `for=` is a hop of unknown origin; `""` stops the walk there.
+    forwarded
+        .into_iter()
+        .map(|element| {
+            element
+                .split(';')
+                .filter_map(|pair| pair.split_once('='))
+                .find(|(name, _)| name.trim().eq_ignore_ascii_case("for"))
+                .map_or("", |(_, value)| value.trim().trim_matches('"'))
+        })
+        .collect()
+}
+
+/// The `User-Agent` header of `req`, truncated.
+pub fn user_agent(req: &HttpRequest) -> Option<String> {
+    let user_agent = req.headers().get(header::USER_AGENT)?;
+    Some(truncate(&String::from_utf8_lossy(user_agent.as_bytes())))
+}
+
+/// An address from a forwarding header: an IP, optionally with a port (`[v6]:port`, `v4:port`).
+fn parse_ip(addr: &str) -> Option<IpAddr> {
+    let addr = addr.trim();
+    addr.parse::<IpAddr>()
+        .ok()
+        .or_else(|| addr.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+        .or_else(|| addr.trim_start_matches('[').trim_end_matches(']').parse().ok())
+}
+
+fn truncate(value: &str) -> String {
+    match value.char_indices().nth(MAX_USER_AGENT) {
+        Some((end, _)) => format!("{}…", &value[..end]),
+        None => value.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forwarded_addresses_parse_with_or_without_port() {
+        let cases = [
+            ("203.0.113.9", "203.0.113.9"),
+            (" 203.0.113.9:4711", "203.0.113.9"),
+            ("2001:db8::1", "2001:db8::1"),
+            ("[2001:db8::1]", "2001:db8::1"),
+            ("[2001:db8::1]:4711", "2001:db8::1"),
+        ];
+        for (input, ip) in cases {
+            assert_eq!(parse_ip(input), Some(ip.parse().unwrap()), "{input}");
+        }
+        for input in ["", "unknown", "_hidden", "example.com:80"] {
+            assert_eq!(parse_ip(input), None, "{input}");
+        }
+    }
+
+    fn headers(pairs: &[(header::HeaderName, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(name.clone(), value.parse().unwrap());
+        }
+        headers
+    }
+
+    fn ips(list: &[&str]) -> Vec<IpAddr> {
+        list.iter().map(|ip| ip.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn client_ip_walks_the_chain_from_the_right_past_trusted_proxies() {
+        let peer = "10.0.0.2".parse().unwrap();
+        let trusted = ips(&["10.0.0.1", "10.0.0.2"]);
+        // The client sent `X-Forwarded-For: 6.6.6.6`; the proxies appended what they saw.
+        let spoofed = headers(&[
+            (header::X_FORWARDED_FOR, "6.6.6.6, 203.0.113.9"),
+            (header::X_FORWARDED_FOR, "10.0.0.1"),
+        ]);
+        assert_eq!(client_ip(peer, &spoofed, &trusted), "203.0.113.9".parse::<IpAddr>().unwrap());
+
+        let direct = "203.0.113.9".parse().unwrap();
+        assert_eq!(client_ip(direct, &spoofed, &trusted), direct, "headers of an untrusted peer");
+        let all_trusted = headers(&[(header::X_FORWARDED_FOR, "10.0.0.1")]);
+        assert_eq!(client_ip(peer, &all_trusted, &trusted), "10.0.0.1".parse::<IpAddr>().unwrap());
+        assert_eq!(client_ip(peer, &HeaderMap::new(), &trusted), peer);
+    }
+
+    #[test]
+    fn forwarded_takes_precedence_and_unknown_hops_end_the_walk() {
+        let peer = "10.0.0.2".parse().unwrap();
+        let trusted = ips(&["10.0.0.1", "10.0.0.2"]);
+        let forwarded = headers(&[
+            (header::FORWARDED, r#"for=6.6.6.6, for="[2001:db8::1]:4711";proto=https"#),
+            (header::FORWARDED, "for=10.0.0.1;by=10.0.0.2"),
+            (header::X_FORWARDED_FOR, "198.51.100.7"),
+        ]);
+        assert_eq!(client_ip(peer, &forwarded, &trusted), "2001:db8::1".parse::<IpAddr>().unwrap());
+
+        let unknown = headers(&[(header::FORWARDED, "for=203.0.113.9, for=unknown, for=10.0.0.1")]);
+        assert_eq!(client_ip(peer, &unknown, &trusted), "10.0.0.1".parse::<IpAddr>().unwrap());
+        let without_for = headers(&[(header::FORWARDED, "for=203.0.113.9, proto=http")]);
+        assert_eq!(client_ip(peer, &without_for, &trusted), peer);
+    }
+
+    #[test]
+    fn user_agents_are_cut_at_a_character_boundary() {
+        assert_eq!(truncate("curl/8.0"), "curl/8.0");
+        let exact = "é".repeat(MAX_USER_AGENT);
+        assert_eq!(truncate(&exact), exact);
+        let long = format!("{exact}é");
+        assert_eq!(truncate(&long), format!("{exact}…"));
+    }
+}
@@ -0,0 +1,179 @@
+//! Cross-site request forgery protection (double-submit cookie, bound to the session).
+//!
+//! Every Inertia page (a full page load or an Inertia visit) gets a random token stored in the
+//! session and mirrored into a readable `XSRF-TOKEN` cookie. Other responses (static files, API
+//! calls, redirects) never create one, so they do not open a server-side session. Inertia's
+//! HTTP client (axios) copies that cookie into an `X-XSRF-TOKEN` header on each request, so
+//! state-changing requests (`POST`, `PUT`, `PATCH`, `DELETE`) must present it, either in that
+//! header or, for plain HTML forms, as a `_token` form field. A cross-site page can make the
+//! browser send our cookies, but it can read neither the cookie nor the session, so it cannot
+//! forge the header.
+//!
+//! Failures, including a session that was never given a token, answer 419 with the Inertia
+//! `PageExpired` page, which issues one. The token is rotated on login ([`rotate`]). Routes can
+//! opt out by name through `csrf.exempt`. The `/admin/` routes are always exempt: they only
+//! accept the bearer admin token, which a browser never attaches on its own, and ignore cookies.
+//! Other `Authorization` headers do not skip the check, since browsers resend cached Basic or
+//! Negotiate credentials with cross-site form posts.
+
+use std::collections::HashMap;
+
+use actix_session::{Session, SessionExt, SessionInsertError};
+use actix_web::{
+    HttpMessage, HttpRequest,
+    body::{EitherBody, MessageBody},
+    cookie::{Cookie, SameSite},
+    dev::{Payload, ServiceRequest, ServiceResponse},
+    http::{Method, StatusCode},
+    middleware::Next,
+    web,
+};
+use inertia_rust::{Inertia, InertiaFacade};
+use rand::{Rng, distr::Alphanumeric};
+
+use crate::config::ConfigHandle;
+
+const TOKEN_KEY: &str = "_csrf_token";
+/// Cookie read by the frontend HTTP client.
+pub const COOKIE_NAME: &str = "XSRF-TOKEN";
+/// Header sent back by the frontend HTTP client.
+pub const HEADER_NAME: &str = "X-XSRF-TOKEN";
+/// Form field accepted from plain HTML form posts.
+pub const FORM_FIELD: &str = "_token";
+/// Status used by Inertia/Laravel for an expired CSRF token ("Page Expired").
+const PAGE_EXPIRED: u16 = 419;
+/// Response header marking an Inertia page, HTML or JSON.
+const X_INERTIA: &str = "x-inertia";
+/// Routes authenticated by the bearer admin token alone (see `handlers::admin::authorize`).
+const TOKEN_ONLY_PREFIX: &str = "/admin/";
+
+/// Token offered to the shared props of a session without one; [`protect`] stores it only if
+/// the response is an Inertia page.
+struct PendingToken(String);
+
+fn generate_token() -> String {
+    rand::rng().sample_iter(&Alphanumeric).take(40).map(char::from).collect()
+}
+
+/// Compare two byte strings in time independent of where they differ.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// The session's current token, if one was issued.
+pub fn token(session: &Session) -> Option<String> {
+    session.get::<String>(TOKEN_KEY).ok().flatten()
+}
+
+/// The `csrfToken` shared prop, for plain HTML forms that post a `_token` field. Shared props are
+/// resolved for every request, so a new token is only proposed here (see [`PendingToken`]).
+pub fn shared_token(req: &HttpRequest) -> Option<String> {
+    if let Some(token) = token(&req.get_session()) {
+        return Some(token);
+    }
+    let token = generate_token();
+    req.extensions_mut().insert(PendingToken(token.clone()));
+    Some(token)
+}
+
+/// Replace the session's token with a fresh one (call on login). The new cookie goes out with
+/// the response.
+pub fn rotate(session: &Session) -> Result<(), SessionInsertError> {
+    session.insert(TOKEN_KEY, generate_token())
+}
+
+/// Token presented by the request: the header, or the `_token` field of a urlencoded body.
+///
+/// Reading the form field consumes the body, so it is put back for the handler. Errors reading
+/// the body (e.g. `413` past the `server.limits` limit) are returned as is.
+async fn presented_token(req: &mut ServiceRequest) -> Result<Option<String>, actix_web::Error> {
+    if let Some(value) = req.headers().get(HEADER_NAME).and_then(|v| v.to_str().ok()) {
+        return Ok(Some(value.to_owned()));
+    }
+
+    let is_form = req.content_type().eq_ignore_ascii_case("application/x-www-form-urlencoded");
+    if !is_form {
+        return Ok(None);
+    }
+
+    let body = req.extract::<web::Bytes>().await?;
+    let field = std::str::from_utf8(&body)
+        .ok()
+        .and_then(|s| web::Query::<HashMap<String, String>>::from_query(s).ok())
+        .and_then(|form| form.get(FORM_FIELD).cloned());
+    req.set_payload(Payload::from(body));
+    Ok(field)
+}
+
+/// Middleware enforcing the token on state-changing requests and publishing it as a cookie.
+///
+/// Must be wrapped inside `SessionMiddleware` and `InertiaMiddleware`, so the 419 page is a
+/// full Inertia response with shared props.
+pub async fn protect(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let cfg = req.app_data::<web::Data<ConfigHandle>>().map(|handle| handle.load());
+    let (enabled, secure) =
+        cfg.as_ref().map_or((true, true), |c| (c.csrf.enabled, c.cookie_secure()));
+    let session = req.get_session();
+
+    let unsafe_method = !matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+    let exempt = req.path().starts_with(TOKEN_ONLY_PREFIX)
+        || cfg.as_ref().is_some_and(|cfg| {
+            req.resource_map()
+                .match_name(req.path())
+                .is_some_and(|name| cfg.csrf.exempt.iter().any(|e| e == name))
+        });
+
+    let valid = if enabled && unsafe_method && !exempt {
+        let presented = presented_token(&mut req).await?;
+        match (presented, token(&session)) {
+            (Some(presented), Some(expected)) => {
+                constant_time_eq(presented.as_bytes(), expected.as_bytes())
+            }
+            _ => false,
+        }
+    } else {
+        true
+    };
+
+    let mut res = if valid {
+        next.call(req).await?.map_into_left_body()
+    } else {
+        let (http_req, _) = req.into_parts();
+        let mut res = Inertia::render(&http_req, "PageExpired".into()).await?;
+        *res.status_mut() = StatusCode::from_u16(PAGE_EXPIRED).unwrap_or(StatusCode::FORBIDDEN);
+        ServiceResponse::new(http_req, res).map_into_right_body()
+    };
+
+    // Keep the token offered to an Inertia page, which may post it back; drop it otherwise.
+    let pending = res.request().extensions_mut().remove::<PendingToken>();
+    if let Some(PendingToken(pending)) = pending
+        && res.headers().contains_key(X_INERTIA)
+        && token(&session).is_none()
+    {
+        session.insert(TOKEN_KEY, pending)?;
+    }
+
+    // Publish the token as it stands after the handler (it may have been rotated or purged).
+    let current = token(&session);
+    let sent = res.request().cookie(COOKIE_NAME).map(|c| c.value().to_owned());
+    if current != sent {
+        let cookie = match current {
+            Some(value) => Cookie::build(COOKIE_NAME, value)
+                .path("/")
+                .secure(secure)
+                .same_site(SameSite::Lax)
+                .finish(),
+            None => {
+                let mut cookie = Cookie::build(COOKIE_NAME, "").path("/").finish();
+                cookie.make_removal();
+                cookie
+            }
+        };
+        res.response_mut().add_cookie(&cookie)?;
+    }
+
+    Ok(res)
+}
@@ -0,0 +1,136 @@
+use actix_web::{
+    HttpRequest, HttpResponse, delete,
+    error::{
+        ErrorBadRequest, ErrorConflict, ErrorNotFound, ErrorServiceUnavailable, ErrorUnauthorized,
+    },
+    get,
+    http::header,
+    web,
+};
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use tracing::info;
+
+use crate::audit::{self, AuditAction, AuditEvent, AuditQuery, AuditSink};
+use crate::config::{AppConfig, ConfigHandle};
+use crate::csrf::constant_time_eq;
+use crate::model_trace;
+use crate::session::SessionRegistry;
+
+/// Check the `Authorization: Bearer <admin_token>` header against `AppConfig.admin_token`.
+///
+/// Admin routes answer 404 when no token is configured, so they are invisible by default.
+pub fn authorize(req: &HttpRequest, cfg: &AppConfig) -> actix_web::Result<()> {
+    let Some(expected) = cfg.admin_token.as_deref() else {
+        return Err(ErrorNotFound("not found"));
+    };
+
+    let presented = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .unwrap_or_default();
+
+    if constant_time_eq(presented.as_bytes(), expected.as_bytes()) {
+        Ok(())
+    } else {
+        Err(ErrorUnauthorized("invalid admin token"))
+    }
+}
+
+/// Who passed [`authorize`], for the audit log. The token is the only admin credential.
+const ACTOR: &str = "admin_token";
+
+/// Revocation only sticks where `session::enforce_registry` checks the registry; with the cookie
+/// store the routes answer 409 rather than report a revocation that changes nothing.
+fn revocable(cfg: &AppConfig) -> actix_web::Result<()> {
+    if cfg.session.store.server_side() {
+        Ok(())
+    } else {
+        Err(ErrorConflict("sessions cannot be revoked with session.store = cookie"))
+    }
+}
+
+/// DELETE /admin/sessions/{session_hash}
+///
+/// Revokes a single session, named by the `session` hash its audit events carry (the raw ID
+/// never leaves the session). Its cookie stops authenticating on the next request. 404 when no
+/// live session has that hash, 409 with the cookie session store.
+#[delete("/admin/sessions/{session_hash}", name = "admin.sessions.revoke")]
+/// [TLA+ Action] SessionModel!ExpireSession
+/// Server-side invalidation of one session, independent of the client's cookie.
+pub async fn revoke_session(
+    req: HttpRequest,
+    path: web::Path<String>,
+    cfg: web::Data<ConfigHandle>,
+    registry: web::Data<dyn SessionRegistry>,
+) -> actix_web::Result<HttpResponse> {
+    let cfg = cfg.load();
+    authorize(&req, &cfg)?;
+    revocable(&cfg)?;
+    let Some((session_id, owner)) = registry.find(&path).await.map_err(ErrorServiceUnavailable)?
+    else {
+        return Err(ErrorNotFound("no live session has that hash"));
+    };
+    registry.revoke(&session_id).await.map_err(ErrorServiceUnavailable)?;
+    model_trace::expire(&session_id);
+    info!(user = %owner, "Session revoked by an admin");
+    let event = AuditEvent::from_request(AuditAction::SessionRevoked, &req)
+        .user(owner)
+        .actor(ACTOR)
+        .session(&session_id);
+    audit::record(&req, event).await;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// DELETE /admin/users/{user}/sessions
+///
+/// Revokes every session of a user ("log out everywhere") and reports how many were live. 409
+/// with the cookie session store.
+#[delete("/admin/users/{user}/sessions", name = "admin.users.sessions.revoke")]
+/// [TLA+ Action] SessionModel!ExpireSession
+/// Applied to every session ID owned by the user.
+pub async fn revoke_user_sessions(
+    req: HttpRequest,
+    path: web::Path<String>,
+    cfg: web::Data<ConfigHandle>,
+    registry: web::Data<dyn SessionRegistry>,
+) -> actix_web::Result<HttpResponse> {
+    let cfg = cfg.load();
+    authorize(&req, &cfg)?;
+    revocable(&cfg)?;
+    let revoked = registry.revoke_user(&path).await.map_err(ErrorServiceUnavailable)?;
+    model_trace::expire_user(&path);
+    info!(user = %path, revoked, "User sessions revoked by an admin");
+    let event = AuditEvent::from_request(AuditAction::SessionRevoked, &req)
+        .user(path.as_str())
+        .actor(ACTOR)
+        .detail(format!("{revoked} sessions"));
+    audit::record(&req, event).await;
+    Ok(HttpResponse::Ok().json(json!({ "revoked": revoked })))
+}
+
+/// GET /admin/audit
+///
+/// Security audit events, newest first, as `{ "events": [...] }`. Filters: `user`, `action`
+/// (e.g. `login_failure`), `since` (inclusive) and `until` (exclusive) as RFC 3339 times, and
+/// `limit` (default 100, at most 1000). Only the `file` and `sqlite` audit sinks can be queried.
+#[get("/admin/audit", name = "admin.audit")]
+pub async fn audit_log(
+    req: HttpRequest,
+    query: web::Query<AuditQuery>,
+    cfg: web::Data<ConfigHandle>,
+    sink: web::Data<dyn AuditSink>,
+) -> actix_web::Result<HttpResponse> {
+    authorize(&req, &cfg.load())?;
+    let mut query = query.into_inner();
+    // Compare in the stored format, whatever offset the caller used.
+    for bound in [&mut query.since, &mut query.until].into_iter().flatten() {
+        let time = DateTime::parse_from_rfc3339(bound)
+            .map_err(|e| ErrorBadRequest(format!("{bound:?} is not an RFC 3339 time: {e}")))?;
+        *bound = audit::timestamp(time.with_timezone(&Utc));
+    }
+    let events = sink.query(&query).await.map_err(ErrorServiceUnavailable)?;
+    Ok(HttpResponse::Ok().json(json!({ "events": events })))
+}
@@ -1,6 +1,28 @@
-use actix_web::{HttpRequest, Responder, get};
+use actix_session::Session;
+use actix_web::{Either, HttpRequest, Responder, get, post, web};
 use inertia_rust::{Inertia, InertiaFacade, InertiaProp, hashmap};
+use serde::Deserialize;
 use serde_json::json;
+use tracing::info;
+use validator::Validate;
+
+use crate::config::ConfigHandle;
+use crate::inertia_session::{flash, redirect_with_errors, validation_errors};
+
+/// A message submitted from the contact page.
+#[derive(Deserialize, Validate)]
+pub struct ContactForm {
+    #[validate(length(min = 1, max = 255, message = "Please tell us your name."))]
+    pub name: String,
+    #[validate(email(message = "Please enter a valid email address."))]
+    pub email: String,
+    #[validate(length(
+        min = 1,
+        max = 5000,
+        message = "Please enter a message (up to 5000 characters)."
+    ))]
+    pub message: String,
+}
 
 /// GET /contact
 ///
@@ -17,3 +39,32 @@ pub async fn contact(req: HttpRequest) -> impl Responder {
 
     Inertia::render_with_props(&req, "Contact".into(), props).await
 }
+
+/// POST /contact
+///
+/// Validates a contact message and redirects back to `/contact`, either with validation errors
+/// or with a `success` flash message. Nothing is delivered yet: there is no mail transport, so
+/// the message is only logged (its length and the number of `contact.recipients`).
+#[post("/contact", name = "contact.submit")]
+pub async fn submit_contact(
+    req: HttpRequest,
+    form: Either<web::Json<ContactForm>, web::Form<ContactForm>>,
+    session: Session,
+    cfg: web::Data<ConfigHandle>,
+) -> actix_web::Result<impl Responder> {
+    let form = match form {
+        Either::Left(json) => json.into_inner(),
+        Either::Right(form) => form.into_inner(),
+    };
+    if let Err(errors) = form.validate() {
+        return Ok(redirect_with_errors(&req, validation_errors(&errors), "/contact"));
+    }
+
+    // Recipients are read per request, so a configuration reload applies immediately.
+    // Only sizes are logged: the sender's name and address and the recipients are personal data.
+    let recipients = cfg.load().contact.recipients.len();
+    info!("Contact message ({} chars) for {recipients} recipient(s)", form.message.chars().count());
+    flash(&session, "success", format!("Thanks, {}! We'll get back to you soon.", form.name))?;
+
+    Ok(web::Redirect::to("/contact").see_other())
+}
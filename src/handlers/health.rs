@@ -0,0 +1,40 @@
+use actix_web::{HttpResponse, Responder, get, web};
+use serde_json::json;
+
+use crate::health::Checks;
+use crate::ssr::{SsrState, SsrSupervisor};
+
+/// GET /healthz (served by `crate::health::probes`, ahead of the session middleware)
+///
+/// Liveness: answers `200` whenever the process can serve requests at all. Nothing is checked, so
+/// a failing dependency never gets the process restarted.
+pub fn healthz() -> HttpResponse {
+    HttpResponse::Ok().json(json!({ "status": "ok" }))
+}
+
+/// GET /readyz (served by `crate::health::probes`, ahead of the session middleware)
+///
+/// Readiness: runs every registered check (see `crate::health`) and reports each one's status,
+/// detail and latency. Responds `503` if any check fails (including once shutdown has started),
+/// `200` otherwise.
+pub async fn readyz(checks: &Checks) -> HttpResponse {
+    let report = checks.run().await;
+    if report.ready {
+        HttpResponse::Ok().json(report)
+    } else {
+        HttpResponse::ServiceUnavailable().json(report)
+    }
+}
+
+/// GET /health/ssr
+///
+/// Reports the SSR supervisor status as JSON. Responds `503` while the SSR server is starting,
+/// unhealthy or stopped (pages still render client-side), `200` when healthy or disabled.
+#[get("/health/ssr", name = "health.ssr")]
+pub async fn ssr_health(ssr: web::Data<SsrSupervisor>) -> impl Responder {
+    let status = ssr.status();
+    match status.state {
+        SsrState::Healthy | SsrState::Disabled => HttpResponse::Ok().json(status),
+        _ => HttpResponse::ServiceUnavailable().json(status),
+    }
+}
@@ -1,19 +1,114 @@
+use std::time::Duration;
+
 use actix_identity::Identity;
-use actix_web::{HttpMessage, HttpRequest, Responder, post};
+use actix_session::Session;
+use actix_web::{
+    Either, HttpMessage, HttpRequest, Responder,
+    error::{ErrorInternalServerError, ErrorServiceUnavailable},
+    post,
+    web::{self, EitherExtractError},
+};
+use inertia_rust::validators::InertiaValidateOrRedirect as _;
+use serde::Deserialize;
+use serde_json::json;
+use tracing::{info, warn};
+use validator::Validate;
+
+use crate::audit::{self, AuditAction, AuditEvent};
+use crate::auth::AuthUser;
+use crate::config::ConfigHandle;
+use crate::csrf;
+use crate::inertia_session::redirect_with_errors;
+use crate::metrics;
+use crate::model_trace;
+use crate::session::{SESSION_ID_KEY, SessionRegistry, registry::generate_session_id};
+use crate::users::{UserStore, verify_credentials};
+
+/// Credentials submitted by the login page (JSON from Inertia, or a classic form post). Missing
+/// fields are empty, so validation reports them like blank ones.
+#[derive(Default, Deserialize, Validate)]
+#[serde(default)]
+pub struct LoginForm {
+    #[validate(length(min = 1, max = 255, message = "Please enter your username."))]
+    pub username: String,
+    #[validate(length(min = 1, max = 1024, message = "Please enter your password."))]
+    pub password: String,
+}
+
+/// The login body as JSON or a form, or why it could not be extracted.
+type LoginBody = Result<
+    Either<web::Json<LoginForm>, web::Form<LoginForm>>,
+    EitherExtractError<actix_web::Error, actix_web::Error>,
+>;
 
 /// POST /login
 ///
-/// Performs a mock authentication and attaches an identity to the session,
-/// then redirects to the home page where the frontend (Inertia) reflects the
-/// authenticated state.
-#[post("/login")]
+/// Validates the submitted `username`/`password`, verifies them against the
+/// configured `UserStore`, attaches an identity to the session and registers
+/// a fresh session ID in the server-side registry, then redirects to the home
+/// page where the frontend (Inertia) reflects the authenticated state.
+///
+/// Invalid input, including a body that does not parse, redirects back with
+/// Inertia validation errors, as do wrong credentials.
+#[post("/login", name = "login")]
 /// [TLA+ Action] SessionModel!Login
 /// Corresponds to the Login action in specs/SessionModel.tla
-/// "Fresh ID" requirement is satisfied by CookieSessionStore's random UUID generation.
-pub async fn login(request: HttpRequest) -> impl Responder {
-    // In a real application, you'd verify credentials here.
-    let _ = Identity::login(&request.extensions(), "User1".into());
+/// "Fresh ID" requirement is satisfied by `generate_session_id` (random, never reused), and
+/// `serverSessions' = [serverSessions EXCEPT ![s] = u]` is the registry insert.
+pub async fn login(
+    request: HttpRequest,
+    form: LoginBody,
+    session: Session,
+    users: web::Data<dyn UserStore>,
+    registry: web::Data<dyn SessionRegistry>,
+    cfg: web::Data<ConfigHandle>,
+) -> actix_web::Result<impl Responder> {
+    let cfg = cfg.load();
+    let form = match form {
+        Ok(Either::Left(json)) => json.into_inner(),
+        Ok(Either::Right(form)) => form.into_inner(),
+        // The body could not be read, e.g. it is over the size limit.
+        Err(EitherExtractError::Bytes(e)) => return Err(e),
+        // Neither JSON nor a form: validated as an empty form.
+        Err(EitherExtractError::Extract(..)) => LoginForm::default(),
+    };
+    let form = match form.validate_or_back(&request) {
+        Ok(form) => form,
+        Err(back) => return Ok(back),
+    };
+
+    let user = verify_credentials(users.get_ref(), &form.username, &form.password)
+        .await
+        .map_err(ErrorInternalServerError)?;
+    metrics::record_login(user.is_some());
+    let Some(user) = user else {
+        warn!(user = %form.username, "Login failed");
+        let event =
+            AuditEvent::from_request(AuditAction::LoginFailure, &request).user(&form.username);
+        audit::record(&request, event).await;
+        let errors =
+            [("username".to_owned(), json!("These credentials do not match our records."))];
+        return Ok(redirect_with_errors(&request, errors.into(), "/login"));
+    };
+
+    Identity::login(&request.extensions(), user.username.clone())?;
+
+    let session_id = generate_session_id();
+    registry
+        .register(&session_id, &user.username, Duration::from_secs(cfg.cookie_ttl_seconds))
+        .await
+        .map_err(ErrorServiceUnavailable)?;
+    metrics::record_session_created();
+    model_trace::login(&user.username, &session_id);
+    let event = AuditEvent::from_request(AuditAction::LoginSuccess, &request)
+        .user(&user.username)
+        .session(&session_id);
+    session.insert(SESSION_ID_KEY, session_id)?;
+    AuthUser::new(&user, cfg.cookie_ttl_seconds).save(&session)?;
+    csrf::rotate(&session)?;
+    info!(user = %user.username, "Logged in");
+    audit::record(&request, event).await;
 
     // Redirect back to the index route so the frontend can render auth state.
-    actix_web::web::Redirect::to("/").see_other()
+    Ok(web::Redirect::to("/").see_other())
 }
@@ -1,18 +1,43 @@
 use actix_identity::Identity;
-use actix_web::{Responder, post, web};
+use actix_session::Session;
+use actix_web::{HttpRequest, Responder, error::ErrorServiceUnavailable, post, web};
+use tracing::info;
+
+use crate::audit::{self, AuditAction, AuditEvent};
+use crate::model_trace;
+use crate::session::{SESSION_ID_KEY, SessionRegistry};
 
 /// POST /logout
 ///
-/// Logs the user out by clearing the attached identity (if any)
-/// and redirects to the home page so the Inertia frontend can
-/// re-render the authentication state.
-#[post("/logout")]
+/// Logs the user out by revoking the session in the server-side registry,
+/// clearing the attached identity (if any), and redirects to the home page
+/// so the Inertia frontend can re-render the authentication state.
+#[post("/logout", name = "logout")]
 /// [TLA+ Action] SessionModel!Logout
 /// Corresponds to the Logout action in specs/SessionModel.tla
-pub async fn logout(user: Option<Identity>) -> impl Responder {
+/// The registry entry is removed first, so the cookie stays invalid even if the client keeps it.
+pub async fn logout(
+    request: HttpRequest,
+    user: Option<Identity>,
+    session: Session,
+    registry: web::Data<dyn SessionRegistry>,
+) -> actix_web::Result<impl Responder> {
     if let Some(user) = user {
+        let mut event = AuditEvent::from_request(AuditAction::Logout, &request);
+        if let Some(session_id) = session.get::<String>(SESSION_ID_KEY)? {
+            registry.revoke(&session_id).await.map_err(ErrorServiceUnavailable)?;
+            if let Ok(name) = user.id() {
+                model_trace::logout(&name, &session_id);
+            }
+            event = event.session(&session_id);
+        }
+        if let Ok(name) = user.id() {
+            info!(user = %name, "Logged out");
+            event = event.user(name);
+        }
         user.logout();
+        audit::record(&request, event).await;
     }
 
-    web::Redirect::to("/").see_other()
+    Ok(web::Redirect::to("/").see_other())
 }
@@ -0,0 +1,14 @@
+use actix_web::{HttpResponse, web};
+
+use crate::session::SessionRegistry;
+
+/// GET {metrics.path} (default `/metrics`)
+///
+/// Every metric in the Prometheus text format (see `crate::metrics`). Served by
+/// `crate::metrics::endpoint` on the app listeners, ahead of the session middleware, or as the
+/// only route of the `metrics.listen` server.
+pub async fn metrics(registry: web::Data<dyn SessionRegistry>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(prometheus::TEXT_FORMAT)
+        .body(crate::metrics::render(registry.get_ref()).await)
+}
@@ -5,23 +5,32 @@ their route functions for convenient `.service(...)` registration in `main`.
 
 Submodules:
 - `index`   -> `GET /`
-- `contact` -> `GET /contact`
+- `contact` -> `GET /contact`, `POST /contact`
 - `login_page` -> `GET /login`
 - `logout_page` -> `GET /logout`
 - `login`   -> `POST /login`
 - `logout`  -> `POST /logout`
 - `echo`    -> `POST /echo`
+- `admin`   -> `DELETE /admin/sessions/{session_hash}`, `DELETE /admin/users/{user}/sessions`,
+  `GET /admin/audit`
+- `health`  -> `GET /health/ssr`; `GET /healthz` and `GET /readyz` are answered by the
+  `health::probes` middleware
+- `metrics` -> `GET /metrics` (`metrics.path`), answered by the `metrics::endpoint` middleware or
+  the `metrics.listen` server
 */
 
 use actix_web::web;
 
+pub mod admin;
 pub mod contact;
 pub mod echo;
+pub mod health;
 pub mod index;
 pub mod login;
 pub mod login_page;
 pub mod logout;
 pub mod logout_page;
+pub mod metrics;
 
 /// Configure all handler services on the given ServiceConfig.
 ///
@@ -30,9 +39,14 @@ pub mod logout_page;
 pub fn init(cfg: &mut web::ServiceConfig) {
     cfg.service(index::index)
         .service(contact::contact)
+        .service(contact::submit_contact)
         .service(login_page::login_page)
         .service(logout_page::logout_page)
         .service(login::login)
         .service(logout::logout)
-        .service(echo::echo);
+        .service(echo::echo)
+        .service(admin::revoke_session)
+        .service(admin::revoke_user_sessions)
+        .service(admin::audit_log)
+        .service(health::ssr_health);
 }
@@ -0,0 +1,259 @@
+//! Readiness checks behind `GET /readyz` (see `handlers::health`).
+//!
+//! A [`Check`] probes one subsystem and reports `ok`, `degraded` (working, but not at full
+//! strength, e.g. pages rendering client-side while SSR is down) or `fail`. [`Checks`] runs every
+//! registered check concurrently, each bounded by [`CHECK_TIMEOUT`], and the instance is ready
+//! when none of them fails. Built-in checks:
+//! - `shutdown`: fails once shutdown has started ([`Readiness`]);
+//! - `ssr`: the SSR supervisor status; degraded unless healthy or disabled;
+//! - `sessions`: pings the session store (Redis) through the session registry;
+//! - `users`: pings the user store (SQLite);
+//! - `manifest`: the Vite manifest was loaded (or the Vite dev server is in use).
+//!
+//! More checks plug in with [`Checks::with`] in `main`.
+//!
+//! [`probes`] answers `GET /healthz` and `GET /readyz` ahead of the session, identity and CSRF
+//! middleware: those load the session on every request, so with Redis down the probe itself would
+//! fail with `500` instead of reporting the failed `sessions` check.
+
+use std::{
+    collections::BTreeMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    Error,
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::Method,
+    middleware::Next,
+    web,
+};
+use futures_util::future::BoxFuture;
+use serde::Serialize;
+
+use crate::{
+    config::{SessionStoreKind, UserStoreKind},
+    handlers,
+    inertia::{ASSETS_VERSION, DEV_MODE},
+    session::SessionRegistry,
+    shutdown::Readiness,
+    ssr::{SsrState, SsrSupervisor},
+    users::UserStore,
+};
+
+/// Time a single check gets before it counts as failed.
+pub const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Result of a check.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    /// Working with reduced function; does not make the instance unready.
+    Degraded,
+    Fail,
+}
+
+/// Status and an optional human-readable detail (backend, state, error).
+#[derive(Clone, Debug, Serialize)]
+pub struct Outcome {
+    pub status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl Outcome {
+    pub fn ok(detail: impl Into<String>) -> Self {
+        Outcome { status: Status::Ok, detail: Some(detail.into()) }
+    }
+
+    pub fn degraded(detail: impl Into<String>) -> Self {
+        Outcome { status: Status::Degraded, detail: Some(detail.into()) }
+    }
+
+    pub fn fail(detail: impl Into<String>) -> Self {
+        Outcome { status: Status::Fail, detail: Some(detail.into()) }
+    }
+}
+
+/// A readiness probe for one subsystem.
+pub trait Check: Send + Sync {
+    /// Key of this check in the report.
+    fn name(&self) -> &'static str;
+
+    fn check(&self) -> BoxFuture<'_, Outcome>;
+}
+
+/// One check's entry in the [`Report`].
+#[derive(Clone, Debug, Serialize)]
+pub struct CheckReport {
+    #[serde(flatten)]
+    pub outcome: Outcome,
+    pub latency_ms: f64,
+}
+
+/// Body of `GET /readyz`.
+#[derive(Clone, Debug, Serialize)]
+pub struct Report {
+    /// `true` when no check failed.
+    pub ready: bool,
+    pub checks: BTreeMap<&'static str, CheckReport>,
+}
+
+/// The registered checks, shared as `web::Data<Checks>`.
+#[derive(Clone, Default)]
+pub struct Checks(Vec<Arc<dyn Check>>);
+
+impl Checks {
+    /// Add a check.
+    pub fn with(mut self, check: impl Check + 'static) -> Self {
+        self.0.push(Arc::new(check));
+        self
+    }
+
+    /// Run every check concurrently.
+    pub async fn run(&self) -> Report {
+        let runs = self.0.iter().map(|check| async move {
+            let started = Instant::now();
+            let outcome =
+                tokio::time::timeout(CHECK_TIMEOUT, check.check()).await.unwrap_or_else(|_| {
+                    Outcome::fail(format!("timed out after {} ms", CHECK_TIMEOUT.as_millis()))
+                });
+            let latency_ms = started.elapsed().as_micros() as f64 / 1000.0;
+            (check.name(), CheckReport { outcome, latency_ms })
+        });
+        let checks: BTreeMap<_, _> =
+            futures_util::future::join_all(runs).await.into_iter().collect();
+        let ready = checks.iter().all(|(_, report)| report.outcome.status != Status::Fail);
+        Report { ready, checks }
+    }
+}
+
+/// Middleware serving the liveness and readiness probes (see the module docs). Every other
+/// request passes through.
+pub async fn probes(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    if matches!(*req.method(), Method::GET | Method::HEAD) {
+        let res = match (req.path(), req.app_data::<web::Data<Checks>>()) {
+            ("/healthz", _) => Some(handlers::health::healthz()),
+            ("/readyz", Some(checks)) => Some(handlers::health::readyz(checks).await),
+            _ => None,
+        };
+        if let Some(res) = res {
+            return Ok(req.into_response(res).map_into_right_body());
+        }
+    }
+    Ok(next.call(req).await?.map_into_left_body())
+}
+
+/// Fails once shutdown has started, so load balancers stop routing here first.
+pub struct ShutdownCheck(pub web::Data<Readiness>);
+
+impl Check for ShutdownCheck {
+    fn name(&self) -> &'static str {
+        "shutdown"
+    }
+
+    fn check(&self) -> BoxFuture<'_, Outcome> {
+        Box::pin(async move {
+            if self.0.is_ready() {
+                Outcome::ok("accepting requests")
+            } else {
+                Outcome::fail("shutting down")
+            }
+        })
+    }
+}
+
+/// SSR supervisor state. Pages still render client-side without SSR, so this only degrades.
+pub struct SsrCheck(pub SsrSupervisor);
+
+impl Check for SsrCheck {
+    fn name(&self) -> &'static str {
+        "ssr"
+    }
+
+    fn check(&self) -> BoxFuture<'_, Outcome> {
+        Box::pin(async move {
+            let status = self.0.status();
+            let state = status.state;
+            match state {
+                SsrState::Healthy | SsrState::Disabled => Outcome::ok(state.to_string()),
+                _ => match status.last_error {
+                    Some(error) => Outcome::degraded(format!("{state}: {error}")),
+                    None => Outcome::degraded(state.to_string()),
+                },
+            }
+        })
+    }
+}
+
+/// Round trip to the session store (a no-op for the cookie and memory stores).
+pub struct SessionCheck {
+    pub kind: SessionStoreKind,
+    pub registry: web::Data<dyn SessionRegistry>,
+}
+
+impl Check for SessionCheck {
+    fn name(&self) -> &'static str {
+        "sessions"
+    }
+
+    fn check(&self) -> BoxFuture<'_, Outcome> {
+        Box::pin(async move {
+            match self.registry.ping().await {
+                Ok(()) => Outcome::ok(self.kind.to_string()),
+                Err(e) => Outcome::fail(format!("{}: {e}", self.kind)),
+            }
+        })
+    }
+}
+
+/// Round trip to the user store.
+pub struct UserStoreCheck {
+    pub kind: UserStoreKind,
+    pub store: web::Data<dyn UserStore>,
+}
+
+impl Check for UserStoreCheck {
+    fn name(&self) -> &'static str {
+        "users"
+    }
+
+    fn check(&self) -> BoxFuture<'_, Outcome> {
+        Box::pin(async move {
+            match self.store.ping().await {
+                Ok(()) => Outcome::ok(self.kind.to_string()),
+                Err(e) => Outcome::fail(format!("{}: {e}", self.kind)),
+            }
+        })
+    }
+}
+
+/// Whether pages can reference built assets: the Vite manifest was loaded at startup, or the
+/// Vite dev server serves them.
+pub struct ManifestCheck;
+
+impl Check for ManifestCheck {
+    fn name(&self) -> &'static str {
+        "manifest"
+    }
+
+    fn check(&self) -> BoxFuture<'_, Outcome> {
+        Box::pin(async move {
+            if DEV_MODE.get().copied().unwrap_or(false) {
+                return Outcome::ok("vite dev server");
+            }
+            match ASSETS_VERSION.get().copied() {
+                Some(version) if version != "development" => {
+                    Outcome::ok(format!("version {version}"))
+                }
+                _ => Outcome::fail("Vite manifest not loaded (run `npm run build`)"),
+            }
+        })
+    }
+}
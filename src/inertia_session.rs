@@ -0,0 +1,148 @@
+//! Session-backed Inertia state that must survive one redirect: validation errors and flash
+//! messages.
+//!
+//! inertia-rust leaves persistence to the app: `Inertia::back_with_errors` only stashes the
+//! errors on the current request (`SessionErrors`), and `InertiaMiddleware` only reads an
+//! `InertiaTemporarySession` from the request extensions. [`bridge`] connects the two through
+//! the actix session:
+//! - on the way in, it moves `errors` and `flash` out of the session into the request, so the
+//!   next page sees them exactly once (shared props `errors` and `flash`);
+//! - on the way out, it saves any newly stashed errors, and re-saves consumed state when Inertia
+//!   answered with a version-mismatch reload (`InertiaSessionToReflash`).
+//!
+//! Handlers use [`flash`] to queue a message and [`redirect_with_errors`] (with
+//! [`validation_errors`]) to send the user back to a form.
+
+use std::collections::{BTreeMap, HashMap};
+
+use actix_session::{Session, SessionExt, SessionInsertError};
+use actix_web::{
+    HttpMessage, HttpRequest,
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+    web::Redirect,
+};
+use inertia_rust::{
+    Inertia, InertiaFacade, InertiaSessionToReflash, InertiaTemporarySession, actix::SessionErrors,
+};
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use validator::{ValidationErrors, ValidationErrorsKind};
+
+const ERRORS_KEY: &str = "_inertia_errors";
+const FLASH_KEY: &str = "_flash";
+
+/// Flash messages consumed by the current request, keyed by kind (`success`, `error`, ...).
+#[derive(Clone, Debug, Default)]
+pub struct IncomingFlash(pub BTreeMap<String, String>);
+
+/// Queue a flash message for the next request (e.g. `flash(&session, "success", "Saved.")`).
+pub fn flash(
+    session: &Session,
+    kind: &str,
+    message: impl Into<String>,
+) -> Result<(), SessionInsertError> {
+    let mut pending =
+        session.get::<BTreeMap<String, String>>(FLASH_KEY).ok().flatten().unwrap_or_default();
+    pending.insert(kind.to_owned(), message.into());
+    session.insert(FLASH_KEY, pending)
+}
+
+/// The flash messages for the current request, as the `flash` shared prop.
+pub fn shared_flash(req: &HttpRequest) -> Value {
+    let flash = req.extensions().get::<IncomingFlash>().cloned().unwrap_or_default();
+    Value::Object(flash.0.into_iter().map(|(k, v)| (k, Value::String(v))).collect())
+}
+
+/// Flatten `validator` errors into Inertia's `errors` shape: one message per field, nested
+/// structs and lists as dotted keys (`address.city`, `items.0.name`).
+///
+/// A rule's `message` is used when set, otherwise its `code`.
+pub fn validation_errors(errors: &ValidationErrors) -> HashMap<String, Value> {
+    fn walk(prefix: &str, errors: &ValidationErrors, out: &mut HashMap<String, Value>) {
+        for (field, kind) in errors.errors() {
+            let key =
+                if prefix.is_empty() { field.to_string() } else { format!("{prefix}.{field}") };
+            match kind {
+                ValidationErrorsKind::Field(list) => {
+                    if let Some(first) = list.first() {
+                        let message = first.message.as_ref().unwrap_or(&first.code);
+                        out.insert(key, Value::String(message.to_string()));
+                    }
+                }
+                ValidationErrorsKind::Struct(inner) => walk(&key, inner, out),
+                ValidationErrorsKind::List(items) => {
+                    for (index, inner) in items {
+                        walk(&format!("{key}.{index}"), inner, out);
+                    }
+                }
+            }
+        }
+    }
+
+    let mut out = HashMap::new();
+    walk("", errors, &mut out);
+    out
+}
+
+/// Stash `errors` for the next request and redirect to `location` (303).
+///
+/// Errors are scoped to the request's `X-Inertia-Error-Bag`, if any, by inertia-rust.
+pub fn redirect_with_errors(
+    req: &HttpRequest,
+    errors: HashMap<String, Value>,
+    location: &'static str,
+) -> Redirect {
+    // `back_with_errors` does the bag scoping and stashing; its own redirect follows `Referer`.
+    let _ = Inertia::back_with_errors(req, errors);
+    Redirect::to(location).see_other()
+}
+
+/// Remove `key` from the session and deserialize it. A missing key leaves the session untouched:
+/// `Session::remove` marks it changed either way, which would rewrite it on every request.
+fn take<T: DeserializeOwned>(session: &Session, key: &str) -> Option<T> {
+    if !session.contains_key(key) {
+        return None;
+    }
+    session.remove_as::<T>(key).and_then(Result::ok)
+}
+
+/// Middleware moving Inertia errors and flash messages between the session and the request.
+///
+/// Must be wrapped inside `SessionMiddleware` and outside `InertiaMiddleware`.
+pub async fn bridge(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let session = req.get_session();
+    let errors = take::<Map<String, Value>>(&session, ERRORS_KEY);
+    let flash = take::<BTreeMap<String, String>>(&session, FLASH_KEY);
+
+    req.extensions_mut().insert(InertiaTemporarySession {
+        errors: Some(errors.unwrap_or_default()),
+        prev_req_url: "/".to_owned(),
+    });
+    req.extensions_mut().insert(IncomingFlash(flash.clone().unwrap_or_default()));
+
+    let res = next.call(req).await?;
+
+    let reflash = res.request().extensions_mut().remove::<InertiaSessionToReflash>();
+    if let Some(InertiaSessionToReflash(consumed)) = reflash {
+        if let Some(errors) = consumed.errors.filter(|e| !e.is_empty()) {
+            session.insert(ERRORS_KEY, errors)?;
+        }
+        if let Some(flash) = flash.filter(|f| !f.is_empty())
+            && session.get::<BTreeMap<String, String>>(FLASH_KEY).ok().flatten().is_none()
+        {
+            session.insert(FLASH_KEY, flash)?;
+        }
+    }
+
+    let stashed = res.request().extensions_mut().remove::<SessionErrors>();
+    if let Some(SessionErrors(errors)) = stashed {
+        session.insert(ERRORS_KEY, errors)?;
+    }
+
+    Ok(res)
+}
@@ -0,0 +1,54 @@
+//! Request body size limits (`server.limits`).
+//!
+//! [`apply`] picks one limit per request (the matched route's entry in `server.limits.routes`,
+//! otherwise the limit for its content type) and installs it as the `PayloadConfig`,
+//! `JsonConfig` and `FormConfig` seen by the body extractors. Using the same value for all three
+//! keeps `Either<Json, Form>` handlers consistent: they buffer the body first, so an oversized
+//! body fails there with `413` instead of surfacing the JSON branch's content-type error.
+//!
+//! Requests announcing a larger `Content-Length` are rejected with `413 Payload Too Large` before
+//! any middleware reads the body; chunked bodies are cut off by the extractors at the same limit.
+
+use std::rc::Rc;
+
+use actix_web::{
+    Error, HttpMessage as _,
+    body::MessageBody,
+    dev::{Extensions, ServiceRequest, ServiceResponse},
+    error::ErrorPayloadTooLarge,
+    http::header,
+    middleware::Next,
+    web,
+};
+
+use crate::config::ConfigHandle;
+
+/// Middleware applying the body limit for the matched route. Wrap it outside anything that reads
+/// the body (e.g. `csrf::protect`).
+pub async fn apply(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let Some(cfg) = req.app_data::<web::Data<ConfigHandle>>().map(|handle| handle.load()) else {
+        return next.call(req).await;
+    };
+    let name = req.resource_map().match_name(req.path());
+    let limit = cfg.server.limits.for_request(name, req.content_type());
+
+    let length = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    if length.is_some_and(|length| length > limit as u64) {
+        return Err(ErrorPayloadTooLarge(format!("request body exceeds {limit} bytes")));
+    }
+
+    let mut extractors = Extensions::new();
+    extractors.insert(web::PayloadConfig::new(limit));
+    extractors.insert(web::JsonConfig::default().limit(limit));
+    extractors.insert(web::FormConfig::default().limit(limit));
+    req.add_data_container(Rc::new(extractors));
+
+    next.call(req).await
+}
@@ -0,0 +1,188 @@
+//! Listening sockets for the HTTP server, from `AppConfig::listen_addrs`.
+//!
+//! Sockets are bound before the server is built, so the chosen port of a `host:0` entry is
+//! known up front and bind errors surface before anything else starts:
+//! - `host:port`: a TCP listener (`[::]:port` for IPv6);
+//! - `unix:/path.sock`: a Unix domain socket; a stale socket file is replaced, the mode from
+//!   `unix_socket_mode` applied, and the file removed on shutdown;
+//! - `systemd`: every socket passed by systemd socket activation (`LISTEN_FDS` starting at fd 3,
+//!   for this process per `LISTEN_PID`). systemd keeps those sockets open across restarts, so
+//!   connections queue instead of being refused.
+//!
+//! Sockets bound here get `server.backlog` as their pending connection queue; systemd sockets
+//! keep the unit's `Backlog=`.
+
+use std::{
+    fmt, fs, io,
+    net::TcpListener,
+    os::{
+        fd::{AsFd, FromRawFd, IntoRawFd, RawFd},
+        unix::{
+            fs::{FileTypeExt, PermissionsExt},
+            net::UnixListener,
+        },
+    },
+    path::{Path, PathBuf},
+};
+
+use socket2::SockRef;
+use tracing::warn;
+
+use crate::config::{ListenAddr, UnixMode};
+
+/// First file descriptor passed by systemd (`SD_LISTEN_FDS_START`).
+const LISTEN_FDS_START: RawFd = 3;
+
+/// A bound socket, ready for `HttpServer::listen` / `listen_uds`.
+pub enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+impl fmt::Display for Listener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Listener::Tcp(listener) => match listener.local_addr() {
+                Ok(addr) => write!(f, "{addr}"),
+                Err(_) => f.write_str("(unknown address)"),
+            },
+            Listener::Unix(listener) => match listener.local_addr() {
+                Ok(addr) => match addr.as_pathname() {
+                    Some(path) => write!(f, "unix:{}", path.display()),
+                    None => f.write_str("unix (unnamed)"),
+                },
+                Err(_) => f.write_str("unix (unknown address)"),
+            },
+        }
+    }
+}
+
+impl Listener {
+    /// The bound TCP port (the chosen one for port 0); `None` for Unix sockets.
+    pub fn port(&self) -> Option<u16> {
+        match self {
+            Listener::Tcp(listener) => listener.local_addr().ok().map(|addr| addr.port()),
+            Listener::Unix(_) => None,
+        }
+    }
+}
+
+/// Bind every address with the given backlog. Returns the listeners and the socket files to
+/// remove on shutdown.
+pub fn bind(
+    addrs: &[ListenAddr],
+    mode: Option<UnixMode>,
+    backlog: u32,
+) -> io::Result<(Vec<Listener>, Vec<PathBuf>)> {
+    let mut listeners = Vec::new();
+    let mut socket_files = Vec::new();
+    for addr in addrs {
+        match addr {
+            ListenAddr::Tcp { host, port } => {
+                let listener = TcpListener::bind((host.as_str(), *port))
+                    .and_then(|listener| set_backlog(listener, backlog))
+                    .map_err(|e| io::Error::new(e.kind(), format!("cannot bind {addr}: {e}")))?;
+                listeners.push(Listener::Tcp(listener));
+            }
+            ListenAddr::Unix(path) => {
+                let listener = bind_unix(path, mode)
+                    .and_then(|listener| set_backlog(listener, backlog))
+                    .map_err(|e| io::Error::new(e.kind(), format!("cannot bind {addr}: {e}")))?;
+                listeners.push(Listener::Unix(listener));
+                socket_files.push(path.clone());
+            }
+            ListenAddr::Systemd => listeners.extend(systemd_listeners()?),
+        }
+    }
+    Ok((listeners, socket_files))
+}
+
+/// Write the address of every TCP listener to `path`, one `host:port` per line, for
+/// `serve --port-file`. The file is renamed into place, so a reader never sees it half written.
+pub fn write_port_file(path: &Path, listeners: &[Listener]) -> io::Result<()> {
+    let addrs: String = listeners
+        .iter()
+        .filter_map(|listener| match listener {
+            Listener::Tcp(listener) => listener.local_addr().ok(),
+            Listener::Unix(_) => None,
+        })
+        .map(|addr| format!("{addr}\n"))
+        .collect();
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    fs::write(&partial, addrs)
+        .and_then(|()| fs::rename(&partial, path))
+        .map_err(|e| io::Error::new(e.kind(), format!("cannot write {}: {e}", path.display())))
+}
+
+/// Remove the socket files created by [`bind`] (actix may already have removed them).
+pub fn cleanup(socket_files: &[PathBuf]) {
+    for path in socket_files {
+        if let Err(e) = fs::remove_file(path)
+            && e.kind() != io::ErrorKind::NotFound
+        {
+            warn!("Cannot remove socket {}: {e}", path.display());
+        }
+    }
+}
+
+fn bind_unix(path: &Path, mode: Option<UnixMode>) -> io::Result<UnixListener> {
+    // A socket file left by a previous run would make bind fail; anything else is not ours.
+    if let Ok(meta) = fs::symlink_metadata(path) {
+        if !meta.file_type().is_socket() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, "not a socket"));
+        }
+        fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    if let Some(UnixMode(mode)) = mode {
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    }
+    Ok(listener)
+}
+
+/// Set the pending connection queue of a bound socket. `listen` again on a listening socket
+/// only updates its backlog (std's `bind` uses a fixed default).
+fn set_backlog<S: AsFd>(listener: S, backlog: u32) -> io::Result<S> {
+    let backlog = i32::try_from(backlog).unwrap_or(i32::MAX);
+    SockRef::from(&listener).listen(backlog)?;
+    Ok(listener)
+}
+
+/// Sockets passed by systemd. Errors if there are none for this process.
+fn systemd_listeners() -> io::Result<Vec<Listener>> {
+    let for_us = std::env::var("LISTEN_PID")
+        .ok()
+        .and_then(|pid| pid.parse::<u32>().ok())
+        .is_none_or(|pid| pid == std::process::id());
+    let count = std::env::var("LISTEN_FDS").ok().and_then(|n| n.parse::<RawFd>().ok());
+    let count = match count {
+        Some(count) if for_us && count > 0 => count,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "listen = systemd, but no sockets were passed (LISTEN_FDS/LISTEN_PID)",
+            ));
+        }
+    };
+
+    (LISTEN_FDS_START..LISTEN_FDS_START + count).map(inherit).collect()
+}
+
+/// Take ownership of an inherited listening socket.
+///
+/// The socket is duplicated (`try_clone` sets close-on-exec) and the inherited descriptor
+/// closed, so child processes such as the SSR server don't keep it open.
+fn inherit(fd: RawFd) -> io::Result<Listener> {
+    // SAFETY: systemd passes open listening sockets in `LISTEN_FDS_START..+LISTEN_FDS`, and
+    // nothing else in this process owns them.
+    let tcp = unsafe { TcpListener::from_raw_fd(fd) };
+    if tcp.local_addr().is_ok() {
+        return Ok(Listener::Tcp(tcp.try_clone()?));
+    }
+    // `local_addr` only fails for non-IP sockets; treat it as a Unix socket.
+    // SAFETY: as above; `into_raw_fd` hands the descriptor over without closing it.
+    let unix = unsafe { UnixListener::from_raw_fd(tcp.into_raw_fd()) };
+    unix.local_addr()?;
+    Ok(Listener::Unix(unix.try_clone()?))
+}
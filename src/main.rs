@@ -1,13 +1,33 @@
 use std::sync::Arc;
 
 use actix_identity::IdentityMiddleware;
-use actix_session::{SessionMiddleware, config::PersistentSession, storage::CookieSessionStore};
-use actix_web::{App, HttpServer, web};
+use actix_session::{SessionMiddleware, config::PersistentSession};
+use actix_web::{App, HttpServer, middleware::from_fn, web};
 use inertia_rust::{InertiaProp, actix::InertiaMiddleware, hashmap};
+use tracing::{info, warn};
 
+mod access_log;
+mod audit;
+mod auth;
+mod cli;
+mod client;
 mod config;
+mod csrf;
 mod handlers;
+mod health;
 mod inertia;
+mod inertia_session;
+mod limits;
+mod listen;
+mod metrics;
+mod model_trace;
+mod reload;
+mod session;
+mod shutdown;
+mod ssr;
+mod telemetry;
+mod tls;
+mod users;
 
 async fn manual_hello() -> &'static str {
     "Hey there!"
@@ -15,61 +35,259 @@ async fn manual_hello() -> &'static str {
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    // Initialize env and logging
+    // Initialize env (logging starts once `serve` has loaded the configuration)
     let _ = dotenvy::dotenv();
-    env_logger::init();
 
-    // Load typed configuration (reads .env and environment)
-    let cfg: config::AppConfig =
-        config::AppConfig::load().map_err(|e| std::io::Error::other(e.to_string()))?;
-    println!("Runtime mode (effective): {}", cfg.mode);
-    let bind_port = cfg.effective_port();
-    let cfg_data: web::Data<config::AppConfig> = web::Data::new(cfg);
+    match cli::Command::parse(std::env::args().skip(1)) {
+        Ok(cli::Command::Serve { port_file }) => serve(port_file.as_deref()).await,
+        Ok(cli::Command::CheckConfig) => {
+            cli::check_config();
+            Ok(())
+        }
+        Ok(cli::Command::PrintConfig(format)) => {
+            cli::print_config(format);
+            Ok(())
+        }
+        Ok(cli::Command::GenKey) => {
+            cli::gen_key();
+            Ok(())
+        }
+        Ok(cli::Command::HashPassword) => {
+            cli::hash_password();
+            Ok(())
+        }
+        Ok(cli::Command::CheckTrace(file)) => {
+            cli::check_trace(&file);
+            Ok(())
+        }
+        Ok(cli::Command::Help) => {
+            cli::help();
+            Ok(())
+        }
+        Err(e) => cli::usage_error(&e),
+    }
+}
+
+async fn serve(port_file: Option<&std::path::Path>) -> std::io::Result<()> {
+    // Load typed configuration (reads .env and environment). Refuse to start with the full
+    // problem report on errors (e.g. unsafe production settings); only warn otherwise.
+    let cfg = cli::load_or_exit();
+    telemetry::init(&cfg);
+    access_log::init(&cfg.access_log)?;
+    match (&cfg.access_log.file, cfg.access_log.enabled) {
+        (_, false) => info!("Access log: off"),
+        (Some(file), true) => info!("Access log: {} to {}", cfg.access_log.format, file.display()),
+        (None, true) => info!("Access log: {} to stdout", cfg.access_log.format),
+    }
+    model_trace::init(cfg.model_trace.as_deref())?;
+    if let Some(file) = &cfg.model_trace {
+        info!("Recording SessionModel transitions to {}", file.display());
+    }
+    info!("Runtime mode (effective): {}", cfg.mode);
+    info!("Public URL: {}", cfg.public_url());
+    for (key, source) in cfg.sources.iter() {
+        info!("Config {key} set by {source}");
+    }
+
+    // Bind every `listen` address up front (ports, Unix sockets, systemd sockets), so the real
+    // port of `host:0` is known and bind errors stop us before anything else starts.
+    let backlog = cfg.server.backlog;
+    let (listeners, mut socket_files) =
+        listen::bind(&cfg.listen_addrs(), cfg.unix_socket_mode, backlog)?;
+    let redirect_addr = cfg.tls.as_ref().and_then(|tls| tls.redirect_http.clone());
+    let (redirect_listeners, _) = listen::bind(redirect_addr.as_slice(), None, backlog)?;
+    let metrics_addr = cfg.metrics.listen.clone().filter(|_| cfg.metrics.enabled);
+    let (metrics_listeners, metrics_socket_files) =
+        listen::bind(metrics_addr.as_slice(), cfg.unix_socket_mode, backlog)?;
+
+    // TLS for the TCP listeners when `tls` is set; the certificate reloads when its files change.
+    let tls_config = cfg.tls.as_ref().map(tls::server_config).transpose()?;
+    let cert_reloader =
+        tls_config.as_ref().map(|(_, resolver)| tls::spawn_reloader(resolver.clone()));
+    let https_port = listeners.iter().find_map(listen::Listener::port).unwrap_or(443);
+
+    // Cookie keyring: the first key seals session cookies, previous keys still open them.
+    if !cfg.secret_keys.is_empty() && cfg.sources.iter().any(|(key, _)| key == "secret_key") {
+        warn!("Both secret_key and secret_keys are set; secret_key is ignored.");
+    }
+    let keyring = session::Keyring::new(cfg.secret_keys())
+        .ok_or_else(|| std::io::Error::other("secret_keys must not be empty"))?;
+    info!("Secret keys: 1 current, {} previous", keyring.previous_len());
+    let keyring_data = web::Data::new(keyring);
 
-    // Initialize Inertia manager (Vite + HBS resolver, SSR enabled)
-    let inertia_manager = inertia::initialize_inertia().await?;
+    // Build the session store selected by `session.store` and the server-side session registry
+    // (both shared by all workers).
+    let (session_backend, session_registry) = session::from_config(&cfg.session)
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    info!("Session store: {}", session_backend.kind());
+
+    // Credential store used by `POST /login`.
+    let user_store = web::Data::from(
+        users::from_config(&cfg.users).map_err(|e| std::io::Error::other(e.to_string()))?,
+    );
+    info!("User store: {}", cfg.users.store);
+
+    // Security audit log (logins, logouts, session expiry and revocation, privilege changes)
+    let audit_sink = web::Data::from(
+        audit::from_config(&cfg.audit).map_err(|e| std::io::Error::other(e.to_string()))?,
+    );
+    info!("Audit sink: {}", cfg.audit.sink);
+
+    // Live configuration: swapped on SIGHUP or config file changes. Settings only read here at
+    // startup (port, keys, stores, SSR, ...) come from this snapshot and need a restart.
+    let config_handle = web::Data::new(config::ConfigHandle::new(cfg));
+    let cfg = config_handle.load();
+    let reloader = reload::spawn(config_handle.clone(), audit_sink.clone());
+
+    // Initialize Inertia manager (Vite + HBS resolver, SSR per `ssr.enabled`) and the SSR-less
+    // instance used while the SSR server is down.
+    let ssr_cfg = &cfg.ssr;
+    let inertia_manager = inertia::initialize_inertia(&cfg).await?;
     let inertia_data = web::Data::new(inertia_manager);
     let inertia_clone = inertia_data.clone();
+    let csr_inertia = web::Data::new(inertia::initialize_csr_inertia(&cfg).await?);
     let vite_dev = crate::inertia::DEV_MODE.get().copied().unwrap_or(false);
-    println!("Vite dev mode detected: {}", vite_dev);
+    info!("Vite dev mode detected: {}", vite_dev);
+
+    // Supervise the SSR server when SSR is on: spawn `node <ssr.entry>` (or watch an external
+    // server). By default SSR is off in dev/test, where we rely on the Vite dev server.
+    let ssr = match inertia_data.get_ssr_url() {
+        Some(_) if ssr_cfg.process == config::SsrProcess::Spawn && !ssr_cfg.entry.exists() => {
+            // Warn if the SSR bundle is missing.
+            let entry = ssr_cfg.entry.display();
+            warn!(
+                "Inertia SSR not started: {entry} not found. Run `npm run build` to generate it."
+            );
+            ssr::SsrSupervisor::disabled(format!("{entry} not found"), csr_inertia)
+        }
+        Some(url) => {
+            info!("Starting SSR server ({}) on {url}...", ssr_cfg.process);
+            ssr::SsrSupervisor::start(ssr_cfg, url.clone(), csr_inertia)
+        }
+        None => {
+            // SSR off (`ssr.enabled`, dev/test mode or Vite dev server detected).
+            info!("SSR disabled (mode={}, vite_dev={})", cfg.mode, vite_dev);
+            ssr::SsrSupervisor::disabled(
+                format!("mode={}, vite_dev={vite_dev}", cfg.mode),
+                csr_inertia,
+            )
+        }
+    };
+    let ssr_data = web::Data::new(ssr.clone());
+
+    // Cleared when shutdown starts (see `shutdown.rs`).
+    let readiness = web::Data::new(shutdown::Readiness::default());
+
+    // Checks behind `GET /readyz`
+    let checks = web::Data::new(
+        health::Checks::default()
+            .with(health::ShutdownCheck(readiness.clone()))
+            .with(health::SsrCheck(ssr.clone()))
+            .with(health::SessionCheck {
+                kind: session_backend.kind(),
+                registry: session_registry.clone(),
+            })
+            .with(health::UserStoreCheck { kind: cfg.users.store, store: user_store.clone() })
+            .with(health::ManifestCheck),
+    );
 
     // Build and bind the server
-    let server = HttpServer::new({
-        let cfg_data = cfg_data.clone();
+    let mut server = HttpServer::new({
+        let cfg = cfg.clone();
+        let config_handle = config_handle.clone();
+        let session_registry = session_registry.clone();
+        let readiness = readiness.clone();
         move || {
-            let cfg = cfg_data.get_ref();
+            let session_backend =
+                session::LiveTtl::new(session_backend.clone(), config_handle.clone());
             App::new()
-                .app_data(cfg_data.clone())
+                .app_data(config_handle.clone())
                 .app_data(inertia_clone.clone())
-                // Identity + Session (cookie-based)
+                .app_data(session_registry.clone())
+                .app_data(user_store.clone())
+                .app_data(audit_sink.clone())
+                .app_data(ssr_data.clone())
+                .app_data(keyring_data.clone())
+                .app_data(readiness.clone())
+                .app_data(checks.clone())
+                // CSRF check for state-changing routes (inside Inertia so 419 renders a full page)
+                .wrap(from_fn(csrf::protect))
+                // Inertia visit counts and SSR render timings (sees the `ssr::fallback` choice)
+                .wrap(from_fn(metrics::inertia))
+                // Client-side rendering while the SSR server is unhealthy
+                .wrap(from_fn(ssr::fallback))
+                // Inertia middleware with shared props (assets version for cache-busting, the
+                // authenticated user, CSRF token, feature flags, flash messages, request ID and
+                // trace context).
+                // Innermost, so the shared-props resolver sees the session and identity after
+                // revocation checks.
+                .wrap(InertiaMiddleware::new().with_shared_props(Arc::new(|req| {
+                    let auth = auth::shared_auth(req);
+                    let csrf_token = csrf::shared_token(req);
+                    let flash = inertia_session::shared_flash(req);
+                    let features = config::shared_features(req);
+                    let request_id = telemetry::shared_request_id(req);
+                    let traceparent = telemetry::shared_traceparent(req);
+                    Box::pin(async move {
+                        hashmap![
+                            "assetsVersion" => InertiaProp::always(
+                                crate::inertia::ASSETS_VERSION
+                                    .get()
+                                    .copied()
+                                    .unwrap_or("development")
+                            ),
+                            "auth" => InertiaProp::always(auth),
+                            "csrfToken" => InertiaProp::always(csrf_token),
+                            "features" => InertiaProp::always(features),
+                            "flash" => InertiaProp::always(flash),
+                            "requestId" => InertiaProp::always(request_id),
+                            "traceparent" => InertiaProp::always(traceparent)
+                        ]
+                    })
+                })))
+                // Identity for the access log, once revocation checks have run
+                .wrap(from_fn(access_log::identify))
+                // Revocation check against the session registry (needs Identity, so it goes first)
+                .wrap(from_fn(session::enforce_registry))
+                // Identity + Session (backend chosen by `session.store`)
                 .wrap(IdentityMiddleware::default())
+                // Validation errors and flash messages: session <-> Inertia request state
+                .wrap(from_fn(inertia_session::bridge))
+                // Re-issue cookies sealed with a previous secret key under the current one
+                .wrap(from_fn(session::keyring::reissue))
                 .wrap(
                     SessionMiddleware::builder(
                         // [TLA+ Invariant] Safety: Session Integrity
-                        // We use CookieSessionStore which generates unique random IDs.
-                        // This satisfies the TLA+ requirement that Login picks a "Fresh" ID
-                        // (not currently in use).
-                        CookieSessionStore::default(),
-                        cfg.secret_key.clone_key(),
+                        // The Redis and memory backends generate unique random session keys and
+                        // the cookie backend carries the state itself, so Login always gets a
+                        // "Fresh" ID (not currently in use), as the TLA+ model requires.
+                        session_backend,
+                        keyring_data.current().clone(),
                     )
                     .cookie_name(cfg.cookie_name.clone())
                     .cookie_secure(cfg.cookie_secure())
                     .session_lifecycle(PersistentSession::default().session_ttl(cfg.cookie_ttl()))
                     .build(),
                 )
-                // Inertia middleware with shared props (assets version for cache-busting)
-                .wrap(InertiaMiddleware::new().with_shared_props(Arc::new(|_req| {
-                    Box::pin(async {
-                        hashmap![
-                            "assetsVersion" => InertiaProp::always(
-                                crate::inertia::ASSETS_VERSION
-                                    .get()
-                                    .copied()
-                                    .unwrap_or("development")
-                            )
-                        ]
-                    })
-                })))
+                // Session cookie `Max-Age` from the live `cookie_ttl_seconds`
+                .wrap(from_fn(session::apply_cookie_ttl))
+                // Open cookies sealed with a previous secret key (before the session loads)
+                .wrap(from_fn(session::keyring::upgrade_cookie))
+                // Request body limits for the matched route (413 before any session work)
+                .wrap(from_fn(limits::apply))
+                // Request counts and latencies by route pattern, status class and method
+                .wrap(from_fn(metrics::track))
+                // One access log record per request (probes and metrics scrapes excluded)
+                .wrap(from_fn(access_log::record))
+                // Prometheus metrics on the app listeners, answered before any session work
+                .wrap(from_fn(metrics::endpoint))
+                // Liveness/readiness probes, answered before any session work
+                .wrap(from_fn(health::probes))
+                // Close keep-alive connections once shutdown has started
+                .wrap(from_fn(shutdown::close_connections))
+                // Request ID and tracing span around everything else
+                .wrap(from_fn(telemetry::request_span))
                 // App routes
                 .configure(handlers::init)
                 .route("/hey", web::get().to(manual_hello))
@@ -78,43 +296,115 @@ async fn main() -> std::io::Result<()> {
                 .service(actix_files::Files::new("/", "./public/").prefer_utf8(true))
         }
     })
-    .bind(("0.0.0.0", bind_port))?;
-
-    // Start the Node SSR process only in production. In dev/test we rely on Vite dev server and skip SSR silently.
-    let is_prod = matches!(cfg_data.get_ref().mode, config::RuntimeMode::Prod);
-    let node = if is_prod && !vite_dev {
-        println!("Starting SSR server on port 5174...");
-        let ssr_entry = "dist/ssr/ssr.js";
-        if std::path::Path::new(ssr_entry).exists() {
-            match inertia_data.start_node_server(ssr_entry.into()) {
-                Ok(n) => Some(n),
-                Err(e) => {
-                    eprintln!(
-                        "Inertia SSR not started: failed to launch SSR server at {ssr_entry}: {e}"
-                    );
-                    None
-                }
-            }
-        } else {
-            // In production, warn if the SSR bundle is missing.
-            eprintln!(
-                "Inertia SSR not started: {ssr_entry} not found. Run `npm run build` to generate it."
-            );
-            None
+    .max_connections(cfg.server.max_connections)
+    .keep_alive(cfg.server.keep_alive())
+    .client_request_timeout(cfg.server.client_request_timeout())
+    .client_disconnect_timeout(cfg.server.client_disconnect_timeout())
+    .shutdown_timeout(cfg.server.shutdown_timeout_seconds)
+    // Signals are handled by `shutdown::Coordinator`, which stops the server in order.
+    .disable_signals();
+    if let Some(workers) = cfg.server.workers {
+        server = server.workers(workers);
+    }
+    let scheme = if tls_config.is_some() { "https" } else { "http" };
+    // `serve --port-file`: the chosen ports of `host:0` addresses, whatever the log level.
+    if let Some(port_file) = port_file {
+        listen::write_port_file(port_file, &listeners)?;
+    }
+    for listener in listeners {
+        match listener {
+            listen::Listener::Tcp(_) => info!("Listening on {scheme}://{listener}"),
+            listen::Listener::Unix(_) => info!("Listening on {listener}"),
         }
+        server = match (listener, &tls_config) {
+            (listen::Listener::Tcp(listener), Some((rustls_config, _))) => {
+                server.listen_rustls_0_23(listener, rustls_config.clone())?
+            }
+            (listen::Listener::Tcp(listener), None) => server.listen(listener)?,
+            (listen::Listener::Unix(listener), _) => server.listen_uds(listener)?,
+        };
+    }
+
+    // Plain-HTTP listener that only redirects to HTTPS (`tls.redirect_http`)
+    let redirect_server = if redirect_listeners.is_empty() {
+        None
     } else {
-        // Dev/test or Vite dev server detected: no SSR.
-        println!("SSR disabled (mode={}, vite_dev={})", cfg_data.get_ref().mode, vite_dev);
+        let https_port = web::Data::new(tls::HttpsPort(https_port));
+        let mut redirect = HttpServer::new({
+            let config_handle = config_handle.clone();
+            move || {
+                App::new()
+                    .app_data(config_handle.clone())
+                    .app_data(https_port.clone())
+                    .default_service(web::to(tls::redirect))
+            }
+        })
+        .workers(1)
+        .shutdown_timeout(cfg.server.shutdown_timeout_seconds)
+        .disable_signals();
+        for listener in redirect_listeners {
+            info!("Redirecting http://{listener} to HTTPS");
+            if let listen::Listener::Tcp(listener) = listener {
+                redirect = redirect.listen(listener)?;
+            }
+        }
+        Some(redirect.run())
+    };
+
+    // Prometheus metrics on their own address (`metrics.listen`) instead of the app listeners
+    let metrics_server = if metrics_listeners.is_empty() {
         None
+    } else {
+        let path = cfg.metrics.path.clone();
+        let mut metrics_server = HttpServer::new({
+            let session_registry = session_registry.clone();
+            let path = path.clone();
+            move || {
+                App::new().app_data(session_registry.clone()).service(
+                    web::resource(&path)
+                        .route(web::get().to(handlers::metrics::metrics))
+                        .route(web::head().to(handlers::metrics::metrics)),
+                )
+            }
+        })
+        .workers(1)
+        .shutdown_timeout(cfg.server.shutdown_timeout_seconds)
+        .disable_signals();
+        for listener in metrics_listeners {
+            info!("Serving metrics on {listener}{path}");
+            metrics_server = match listener {
+                listen::Listener::Tcp(listener) => metrics_server.listen(listener)?,
+                listen::Listener::Unix(listener) => metrics_server.listen_uds(listener)?,
+            };
+        }
+        socket_files.extend(metrics_socket_files);
+        Some(metrics_server.run())
     };
 
-    // Run the server
-    let res = server.run().await;
+    // Run the server(s) until SIGTERM/SIGINT, then shut down in order: readiness, listeners,
+    // in-flight requests, background tasks, SSR, sessions, logs.
+    let mut running: Vec<_> = vec![server.run()];
+    running.extend(redirect_server);
+    running.extend(metrics_server);
+    let servers = running.iter().map(|server| server.handle()).collect();
+    let running = actix_web::rt::spawn(async move {
+        futures_util::future::join_all(running).await.into_iter().collect::<std::io::Result<()>>()
+    });
 
-    // Ensure SSR node is terminated if it was started
-    if let Some(node) = node {
-        std::mem::drop(node.kill());
+    let trigger = shutdown::signal().await;
+    let mut tasks = vec![("config watcher", reloader)];
+    tasks.extend(cert_reloader.map(|task| ("certificate reloader", task)));
+    let drained = shutdown::Coordinator {
+        readiness,
+        servers,
+        shutdown_timeout: std::time::Duration::from_secs(cfg.server.shutdown_timeout_seconds),
+        socket_files,
+        tasks,
+        ssr,
+        sessions: session_registry,
     }
+    .run(trigger)
+    .await;
 
-    res
+    if drained { running.await.map_err(std::io::Error::other)? } else { Ok(()) }
 }
@@ -0,0 +1,364 @@
+//! Prometheus metrics (`AppConfig::metrics`), served as text by `handlers::metrics`.
+//!
+//! Everything is recorded into one process-wide [`Registry`]:
+//! - `http_requests_total` and `http_request_duration_seconds`, labelled by `method`, `route`
+//!   (the matched route pattern such as `/admin/sessions/{session_hash}`, `static` for the static
+//!   files service, `unmatched` otherwise)
+//!   and `status` (`2xx`, `4xx`, ...), recorded by [`track`];
+//! - `sessions_active` (refreshed on every scrape, see `SessionRegistry::sweep`),
+//!   `sessions_created_total` and `sessions_expired_total`;
+//! - `logins_total`, labelled `result="success|failure"`;
+//! - `ssr_page_duration_seconds` (the whole request, handler included: Inertia makes the SSR
+//!   call internally, so it cannot be timed on its own) and `ssr_render_failures_total` for full
+//!   page loads rendered through the SSR server, and `inertia_visits_total` labelled
+//!   `kind="full|partial"`, recorded by [`inertia`], which also traces those page loads in an
+//!   `ssr_render` span.
+//!
+//! [`endpoint`] answers `GET {metrics.path}` on the app listeners ahead of the session
+//! middleware, like the health probes, to requests bearing the admin token; with
+//! `metrics.listen` the endpoint moves to its own server instead, without a token (see `main`).
+
+use std::{
+    sync::LazyLock,
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    Error,
+    body::{self, EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::{Method, StatusCode, header},
+    middleware::Next,
+    web,
+};
+use inertia_rust::Inertia;
+use prometheus::{
+    Encoder as _, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+    Opts, Registry, TextEncoder,
+};
+use tracing::{Instrument as _, Span, error, field::Empty};
+
+use crate::{config::ConfigHandle, handlers, session::SessionRegistry};
+
+/// Time the session store gets to count live sessions during a scrape.
+const SESSION_COUNT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Request header marking an Inertia visit (as opposed to a full page load).
+const X_INERTIA: &str = "x-inertia";
+/// Request header naming the component of a partial reload.
+const X_INERTIA_PARTIAL_COMPONENT: &str = "x-inertia-partial-component";
+/// Set by `www/root.hbs` when the page was rendered by the SSR server.
+const SSR_MARKER: &[u8] = br#"<meta name="ssr" content="true">"#;
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+/// Handles to every metric, registered in `registry`.
+struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_duration: HistogramVec,
+    sessions_active: IntGauge,
+    sessions_created: IntCounter,
+    sessions_expired: IntCounter,
+    logins: IntCounterVec,
+    ssr_page_duration: Histogram,
+    ssr_failures: IntCounter,
+    inertia_visits: IntCounterVec,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        // Names and label sets are static, so registration cannot fail.
+        fn register<M: prometheus::core::Collector + Clone + 'static>(
+            registry: &Registry,
+            metric: prometheus::Result<M>,
+        ) -> M {
+            let metric = metric.expect("valid metric definition");
+            registry.register(Box::new(metric.clone())).expect("unique metric name");
+            metric
+        }
+        let registry = Registry::new();
+        let http_labels = &["method", "route", "status"];
+
+        Metrics {
+            http_requests: register(
+                &registry,
+                IntCounterVec::new(
+                    Opts::new("http_requests_total", "HTTP requests handled"),
+                    http_labels,
+                ),
+            ),
+            http_duration: register(
+                &registry,
+                HistogramVec::new(
+                    HistogramOpts::new(
+                        "http_request_duration_seconds",
+                        "Time from receiving a request to sending the response head",
+                    ),
+                    http_labels,
+                ),
+            ),
+            sessions_active: register(
+                &registry,
+                IntGauge::new("sessions_active", "Live sessions in the session registry"),
+            ),
+            sessions_created: register(
+                &registry,
+                IntCounter::new("sessions_created_total", "Sessions registered by a login"),
+            ),
+            sessions_expired: register(
+                &registry,
+                IntCounter::new(
+                    "sessions_expired_total",
+                    "Sessions removed from the registry because their TTL ran out",
+                ),
+            ),
+            logins: register(
+                &registry,
+                IntCounterVec::new(Opts::new("logins_total", "Login attempts"), &["result"]),
+            ),
+            ssr_page_duration: register(
+                &registry,
+                Histogram::with_opts(HistogramOpts::new(
+                    "ssr_page_duration_seconds",
+                    "Time to serve a full page load rendered through the SSR server, from \
+                     receiving the request to the rendered body",
+                )),
+            ),
+            ssr_failures: register(
+                &registry,
+                IntCounter::new(
+                    "ssr_render_failures_total",
+                    "Full page loads that fell back to client-side rendering because the SSR \
+                     server failed",
+                ),
+            ),
+            inertia_visits: register(
+                &registry,
+                IntCounterVec::new(
+                    Opts::new("inertia_visits_total", "Inertia page responses"),
+                    &["kind"],
+                ),
+            ),
+            registry,
+        }
+    }
+}
+
+/// Record a login attempt with valid input: the credentials matched or they did not.
+pub fn record_login(success: bool) {
+    let result = if success { "success" } else { "failure" };
+    METRICS.logins.with_label_values(&[result]).inc();
+}
+
+/// Record a session registered by a login.
+pub fn record_session_created() {
+    METRICS.sessions_created.inc();
+}
+
+/// Record sessions a registry dropped after their TTL ran out.
+pub fn record_sessions_expired(count: usize) {
+    METRICS.sessions_expired.inc_by(count as u64);
+}
+
+/// Current value of every metric in the Prometheus text format, after refreshing
+/// `sessions_active` from `registry`. The gauge keeps its last value while the session store is
+/// unreachable.
+pub async fn render(registry: &dyn SessionRegistry) -> Vec<u8> {
+    if let Ok(Ok(live)) = tokio::time::timeout(SESSION_COUNT_TIMEOUT, registry.sweep()).await {
+        METRICS.sessions_active.set(i64::try_from(live).unwrap_or(i64::MAX));
+    }
+    let mut buffer = Vec::new();
+    if let Err(e) = TextEncoder::new().encode(&METRICS.registry.gather(), &mut buffer) {
+        error!("Cannot encode metrics: {e}");
+    }
+    buffer
+}
+
+/// Middleware serving `GET {metrics.path}` when the endpoint is enabled on the app listeners.
+/// Scrapes must carry the admin token (see `handlers::admin::authorize`): without one
+/// configured the endpoint answers 404, like the admin routes. Every other request passes
+/// through.
+pub async fn endpoint(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let cfg = req.app_data::<web::Data<ConfigHandle>>().map(|handle| handle.load());
+    let registry = req.app_data::<web::Data<dyn SessionRegistry>>().cloned();
+    if let (Some(cfg), Some(registry)) = (cfg, registry)
+        && cfg.metrics.enabled
+        && cfg.metrics.listen.is_none()
+        && req.path() == cfg.metrics.path
+        && matches!(*req.method(), Method::GET | Method::HEAD)
+    {
+        if let Err(e) = handlers::admin::authorize(req.request(), &cfg) {
+            return Ok(req.error_response(e).map_into_right_body());
+        }
+        let res = handlers::metrics::metrics(registry).await;
+        return Ok(req.into_response(res).map_into_right_body());
+    }
+    Ok(next.call(req).await?.map_into_left_body())
+}
+
+/// Middleware recording `http_requests_total` and `http_request_duration_seconds`, including
+/// requests that fail in an inner middleware (e.g. `413` from `limits::apply`).
+pub async fn track(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let started = Instant::now();
+    let method = method_label(req.method());
+    let res = next.call(req).await;
+
+    let (route, status) = match &res {
+        Ok(res) => {
+            let route = res.request().match_pattern();
+            (route, res.status())
+        }
+        Err(e) => (None, e.as_response_error().status_code()),
+    };
+    // The static files service is mounted at `/`, which it reports as an empty pattern.
+    let route = match route {
+        None => "unmatched".to_owned(),
+        Some(route) if route.is_empty() => "static".to_owned(),
+        Some(route) => route,
+    };
+    let labels = [method, route.as_str(), status_class(status)];
+    METRICS.http_requests.with_label_values(&labels).inc();
+    METRICS.http_duration.with_label_values(&labels).observe(started.elapsed().as_secs_f64());
+    res
+}
+
+/// Middleware counting Inertia page responses by kind and timing full page loads rendered
+/// through the SSR server, from the request to the rendered body. Must be wrapped inside `ssr::fallback` (i.e. registered before it
+/// with `.wrap`), so it sees whether this request renders with SSR at all.
+///
+/// Navigations (requests accepting `text/html`) that may render through the SSR server run in an
+/// `ssr_render` span, whose `ssr.outcome` is `rendered`, `fallback` (client-side after a failed
+/// SSR call) or `skipped` (the response was not an Inertia page).
+pub async fn inertia(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let started = Instant::now();
+    let visit = req.headers().contains_key(X_INERTIA);
+    let partial = visit && req.headers().contains_key(X_INERTIA_PARTIAL_COMPONENT);
+    let ssr =
+        req.app_data::<web::Data<Inertia>>().is_some_and(|inertia| inertia.get_ssr_url().is_some());
+    let accepts_html = req
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("text/html"));
+    let span = if !visit && ssr && accepts_html {
+        tracing::info_span!("ssr_render", otel.name = "ssr render", ssr.outcome = Empty)
+    } else {
+        Span::none()
+    };
+    let res = next.call(req).instrument(span.clone()).await?;
+
+    // Inertia marks every page it renders, JSON or HTML, with `X-Inertia`.
+    if !res.headers().contains_key(X_INERTIA) {
+        span.record("ssr.outcome", "skipped");
+        return Ok(res.map_into_left_body());
+    }
+    let kind = if partial { "partial" } else { "full" };
+    METRICS.inertia_visits.with_label_values(&[kind]).inc();
+
+    let html = res
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/html"));
+    if visit || !ssr || !html {
+        span.record("ssr.outcome", "skipped");
+        return Ok(res.map_into_left_body());
+    }
+
+    // A failed SSR call does not fail the request: Inertia renders the page client-side, which
+    // only shows in the template's `ssr` meta tag.
+    let (req, res) = res.into_parts();
+    let (res, body) = res.into_parts();
+    let body = body::to_bytes(body)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(e.into().to_string()))?;
+    METRICS.ssr_page_duration.observe(started.elapsed().as_secs_f64());
+    if body.windows(SSR_MARKER.len()).any(|window| window == SSR_MARKER) {
+        span.record("ssr.outcome", "rendered");
+    } else {
+        span.record("ssr.outcome", "fallback");
+        METRICS.ssr_failures.inc();
+    }
+    Ok(ServiceResponse::new(req, res.set_body(body).map_into_boxed_body()).map_into_right_body())
+}
+
+/// Method label; anything outside the standard methods is `other`, to bound the label values.
+fn method_label(method: &Method) -> &'static str {
+    match *method {
+        Method::GET => "GET",
+        Method::HEAD => "HEAD",
+        Method::POST => "POST",
+        Method::PUT => "PUT",
+        Method::PATCH => "PATCH",
+        Method::DELETE => "DELETE",
+        Method::OPTIONS => "OPTIONS",
+        _ => "other",
+    }
+}
+
+fn status_class(status: StatusCode) -> &'static str {
+    match status.as_u16() {
+        100..=199 => "1xx",
+        200..=299 => "2xx",
+        300..=399 => "3xx",
+        400..=499 => "4xx",
+        _ => "5xx",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::{App, HttpResponse, middleware::from_fn, test};
+
+    use super::*;
+    use crate::{
+        config::{AppConfig, ConfigHandle},
+        session::MemorySessionRegistry,
+    };
+
+    /// The status of `GET /metrics` with `authorization`, on an app listener configured by
+    /// `values`.
+    async fn scrape(values: serde_json::Value, authorization: Option<&str>) -> StatusCode {
+        let cfg: AppConfig = serde_json::from_value(values).unwrap();
+        let registry: Arc<dyn SessionRegistry> = Arc::new(MemorySessionRegistry::default());
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(ConfigHandle::new(cfg)))
+                .app_data(web::Data::from(registry))
+                .wrap(from_fn(endpoint))
+                .default_service(web::to(HttpResponse::Ok)),
+        )
+        .await;
+        let mut req = test::TestRequest::get().uri("/metrics");
+        if let Some(authorization) = authorization {
+            req = req.insert_header((header::AUTHORIZATION, authorization));
+        }
+        let res = test::call_service(&app, req.to_request()).await;
+        if res.status() == StatusCode::OK {
+            let content_type = res.headers().get(header::CONTENT_TYPE).unwrap();
+            assert_eq!(content_type, prometheus::TEXT_FORMAT, "served by the endpoint");
+        }
+        res.status()
+    }
+
+    #[actix_web::test]
+    async fn the_endpoint_on_the_app_listeners_needs_the_admin_token() {
+        let token = serde_json::json!({ "admin_token": "t" });
+        assert_eq!(scrape(serde_json::json!({}), None).await, StatusCode::NOT_FOUND);
+        assert_eq!(scrape(token.clone(), None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(scrape(token.clone(), Some("Bearer x")).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(scrape(token, Some("Bearer t")).await, StatusCode::OK);
+    }
+}
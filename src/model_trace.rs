@@ -0,0 +1,360 @@
+//! Runtime trace of the session transitions of `specs/SessionModel.tla`, and its checker.
+//!
+//! With `model_trace` set, [`init`] truncates that file and writes the model's `Init` state; the
+//! code annotated `[TLA+ Action]` then reports each transition it performs:
+//! - `Login`: [`login`], once the fresh session ID is registered;
+//! - `Logout`: [`logout`], once the session ID is revoked;
+//! - `ExpireSession`: [`expire`] and [`expire_user`] for admin revocations, and [`access`] when
+//!   the registry no longer knows a session the trace still holds (its TTL ran out);
+//! - `AccessProtected`: [`access`], when `session::enforce_registry` honours an identity.
+//!
+//! Each transition is one JSON line (NDJSON) with the abstract state after it:
+//!
+//! ```json
+//! {"step":1,"action":"Login","user":"alice","session":"9f2c...","state":{
+//!   "serverSessions":{"9f2c...":"alice"},"clientCookies":[{"u":"alice","s":"9f2c..."}]}}
+//! ```
+//!
+//! `serverSessions` maps every session ID seen so far to its owner or `"NULL"`, and
+//! `clientCookies` holds every cookie issued: the server cannot see a client drop one
+//! (`ClientClearsCookies`), and the model lets clients keep cookies after logout. Session IDs are
+//! written as `audit::session_hash`es, never in the clear.
+//!
+//! [`check`] replays a trace: every state must satisfy `TypeOK` and `SessionIntegrity`, every
+//! action must be enabled in the state before it, and must lead to the recorded state. A request
+//! granted with a revoked session, or a login handing out an ID some client still holds, shows up
+//! as a step that breaks one of these. `hello-actix check-trace FILE` runs it.
+//!
+//! The trace is kept per process and starts from an empty registry, so record a single instance
+//! with the `memory` or `cookie` session store. Lines are written synchronously under a lock and
+//! the state is copied into every line: fine for tests and staging, not for production traffic.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::OpenOptions,
+    io::{self, BufRead, Write as _},
+    os::unix::fs::OpenOptionsExt as _,
+    path::Path,
+    sync::{Mutex, OnceLock},
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::audit::session_hash;
+
+/// `"NULL"` of the model: a session ID owned by nobody.
+pub const NULL: &str = "NULL";
+
+/// Where transitions are recorded; set by [`init`].
+static RECORDER: OnceLock<Mutex<Recorder>> = OnceLock::new();
+
+/// A transition of `SessionModel.tla`, or its initial state.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Action {
+    Init,
+    Login,
+    Logout,
+    ExpireSession,
+    AccessProtected,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// A cookie held by a client: `[u: Users, s: SessionIDs]`.
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub struct Cookie {
+    pub u: String,
+    pub s: String,
+}
+
+/// The model's variables.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct State {
+    /// Session ID -> owner, or [`NULL`].
+    pub server_sessions: BTreeMap<String, String>,
+    /// Sorted, without duplicates.
+    pub client_cookies: Vec<Cookie>,
+}
+
+impl State {
+    /// Owner of `session`, `None` for [`NULL`] or an ID not seen yet.
+    fn owner(&self, session: &str) -> Option<&str> {
+        self.server_sessions.get(session).map(String::as_str).filter(|owner| *owner != NULL)
+    }
+
+    fn holds(&self, user: &str, session: &str) -> bool {
+        self.client_cookies.iter().any(|c| c.u == user && c.s == session)
+    }
+
+    /// Why `action` is not enabled in this state, if it is not.
+    fn guard(&self, action: Action, user: &str, session: &str) -> Result<(), String> {
+        match action {
+            Action::Init => Err("Init after the first step".to_owned()),
+            Action::Login if self.owner(session).is_some() => {
+                Err(format!("Login reuses session {session}, which is not NULL"))
+            }
+            Action::Login if self.client_cookies.iter().any(|c| c.s == session) => {
+                Err(format!("Login reuses session {session}, still held by a client"))
+            }
+            Action::Logout | Action::AccessProtected if !self.holds(user, session) => {
+                Err(format!("{action}({user}) without a cookie [u |-> {user}, s |-> {session}]"))
+            }
+            Action::Logout | Action::AccessProtected if self.owner(session) != Some(user) => {
+                Err(format!(
+                    "{action}({user}) with session {session} owned by {}",
+                    self.owner(session).unwrap_or(NULL)
+                ))
+            }
+            Action::ExpireSession if self.owner(session).is_none() => {
+                Err(format!("ExpireSession of session {session}, which is NULL"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Apply the effect of `action`, without checking its guard.
+    fn apply(&mut self, action: Action, user: &str, session: &str) {
+        match action {
+            Action::Init | Action::AccessProtected => {}
+            Action::Login => {
+                self.server_sessions.insert(session.to_owned(), user.to_owned());
+                let cookie = Cookie { u: user.to_owned(), s: session.to_owned() };
+                if let Err(at) = self.client_cookies.binary_search(&cookie) {
+                    self.client_cookies.insert(at, cookie);
+                }
+            }
+            Action::Logout | Action::ExpireSession => {
+                self.server_sessions.insert(session.to_owned(), NULL.to_owned());
+            }
+        }
+    }
+}
+
+/// `TypeOK`, with `SessionIDs` the IDs seen so far and `Users` any non-empty name.
+fn type_ok(state: &State) -> Result<(), String> {
+    if let Some((session, _)) = state.server_sessions.iter().find(|(s, _)| !is_session_id(s)) {
+        return Err(format!("TypeOK: {session:?} is not a session ID"));
+    }
+    if let Some((session, _)) = state.server_sessions.iter().find(|(_, owner)| owner.is_empty()) {
+        return Err(format!("TypeOK: serverSessions[{session}] is neither a user nor NULL"));
+    }
+    for cookie in &state.client_cookies {
+        if cookie.u.is_empty() || cookie.u == NULL {
+            return Err(format!("TypeOK: cookie user {:?} is not a user", cookie.u));
+        }
+        if !state.server_sessions.contains_key(&cookie.s) {
+            return Err(format!("TypeOK: cookie session {} is not in SessionIDs", cookie.s));
+        }
+    }
+    if !state.client_cookies.is_sorted() || state.client_cookies.windows(2).any(|w| w[0] == w[1]) {
+        return Err("TypeOK: clientCookies is not a sorted set".to_owned());
+    }
+    Ok(())
+}
+
+/// `SessionIntegrity`: a cookie's session, when not NULL, belongs to the cookie's user.
+fn session_integrity(state: &State) -> Result<(), String> {
+    for cookie in &state.client_cookies {
+        if let Some(owner) = state.owner(&cookie.s).filter(|owner| *owner != cookie.u) {
+            return Err(format!(
+                "SessionIntegrity: cookie [u |-> {}, s |-> {}] but the session is owned by {owner}",
+                cookie.u, cookie.s
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// The format of [`session_hash`]: 32 lowercase hex digits.
+fn is_session_id(s: &str) -> bool {
+    s.len() == 32 && s.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+/// One line of a trace.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Step {
+    pub step: u64,
+    pub action: Action,
+    /// The user of `Login`, `Logout` and `AccessProtected`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// The session the action acts on (`Login` picks it, the others find it in a cookie).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session: Option<String>,
+    /// State after the action.
+    pub state: State,
+}
+
+struct Recorder {
+    file: std::fs::File,
+    step: u64,
+    state: State,
+}
+
+impl Recorder {
+    fn record(&mut self, action: Action, user: Option<&str>, session: &str) {
+        self.state.apply(action, user.unwrap_or_default(), session);
+        self.step += 1;
+        let step = Step {
+            step: self.step,
+            action,
+            user: user.map(str::to_owned),
+            session: Some(session.to_owned()),
+            state: self.state.clone(),
+        };
+        self.write(&step);
+    }
+
+    fn write(&mut self, step: &Step) {
+        let written = serde_json::to_vec(step).map_err(io::Error::from).and_then(|mut line| {
+            line.push(b'\n');
+            self.file.write_all(&line)
+        });
+        if let Err(e) = written {
+            error!(action = %step.action, "Cannot write the model trace: {e}");
+        }
+    }
+}
+
+/// Start recording to `path` (created with mode `0600`, truncated) if set. Call once at startup.
+pub fn init(path: Option<&Path>) -> io::Result<()> {
+    let Some(path) = path else {
+        return Ok(());
+    };
+    let file = OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(path)?;
+    let mut recorder = Recorder { file, step: 0, state: State::default() };
+    recorder.write(&Step {
+        step: 0,
+        action: Action::Init,
+        user: None,
+        session: None,
+        state: State::default(),
+    });
+    let _ = RECORDER.set(Mutex::new(recorder));
+    Ok(())
+}
+
+/// Run `f` on the recorder, if recording.
+fn with_recorder(f: impl FnOnce(&mut Recorder)) {
+    if let Some(recorder) = RECORDER.get() {
+        f(&mut recorder.lock().unwrap_or_else(|e| e.into_inner()));
+    }
+}
+
+/// `Login(user)`: `session_id` was registered to `user` and sent in its cookie.
+pub fn login(user: &str, session_id: &str) {
+    with_recorder(|r| r.record(Action::Login, Some(user), &session_hash(session_id)));
+}
+
+/// `Logout(user)`: `session_id`, from `user`'s cookie, was revoked.
+pub fn logout(user: &str, session_id: &str) {
+    with_recorder(|r| r.record(Action::Logout, Some(user), &session_hash(session_id)));
+}
+
+/// `ExpireSession`: `session_id` was revoked by an admin.
+pub fn expire(session_id: &str) {
+    with_recorder(|r| r.record(Action::ExpireSession, None, &session_hash(session_id)));
+}
+
+/// `ExpireSession` for each session of `user` in the trace: every session of theirs was revoked.
+pub fn expire_user(user: &str) {
+    with_recorder(|r| {
+        let sessions: Vec<String> = r
+            .state
+            .server_sessions
+            .iter()
+            .filter(|(_, owner)| *owner == user)
+            .map(|(session, _)| session.clone())
+            .collect();
+        for session in sessions {
+            r.record(Action::ExpireSession, None, &session);
+        }
+    });
+}
+
+/// A request presented `user`'s identity with `session_id`, which the registry says belongs to
+/// `owner`. Recorded as `AccessProtected(user)` when that grants access, and as `ExpireSession`
+/// when the registry dropped a session the trace still holds. Other refusals change nothing.
+pub fn access(user: &str, session_id: &str, owner: Option<&str>) {
+    with_recorder(|r| {
+        let session = session_hash(session_id);
+        if owner == Some(user) {
+            r.record(Action::AccessProtected, Some(user), &session);
+        } else if owner.is_none() && r.state.owner(&session).is_some() {
+            r.record(Action::ExpireSession, None, &session);
+        }
+    });
+}
+
+/// A trace line that breaks the model.
+#[derive(Debug)]
+pub struct Violation {
+    /// 1-based; 0 for a trace without lines.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for Violation {}
+
+/// Replay `trace` against the model and return how many transitions it holds. Fails at the first
+/// line that is not a step, skips a step number, breaks `TypeOK` or `SessionIntegrity`, takes an
+/// action that is not enabled, or records a state other than the action's successor.
+pub fn check(trace: impl BufRead) -> Result<u64, Violation> {
+    let mut previous: Option<State> = None;
+    let mut steps = 0;
+    for (index, line) in trace.lines().enumerate() {
+        let violation = |message: String| Violation { line: index + 1, message };
+        let line = line.map_err(|e| violation(e.to_string()))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let step: Step =
+            serde_json::from_str(&line).map_err(|e| violation(format!("not a trace step: {e}")))?;
+        if step.step != steps + u64::from(previous.is_some()) {
+            return Err(violation(format!("step {} out of sequence", step.step)));
+        }
+        type_ok(&step.state).map_err(violation)?;
+        session_integrity(&step.state).map_err(violation)?;
+
+        let Some(before) = previous else {
+            if step.action != Action::Init || step.state != State::default() {
+                return Err(violation("the trace does not start with Init".to_owned()));
+            }
+            previous = Some(step.state);
+            continue;
+        };
+        let Some(session) = step.session.as_deref() else {
+            return Err(violation(format!("{} without a session", step.action)));
+        };
+        let user = match (step.action, step.user.as_deref()) {
+            (Action::ExpireSession, _) => "",
+            (_, Some(user)) => user,
+            (action, None) => return Err(violation(format!("{action} without a user"))),
+        };
+        before.guard(step.action, user, session).map_err(violation)?;
+        let mut after = before;
+        after.apply(step.action, user, session);
+        if after != step.state {
+            return Err(violation(format!("state after {} differs from the model", step.action)));
+        }
+        previous = Some(after);
+        steps = step.step;
+    }
+    match previous {
+        Some(_) => Ok(steps),
+        None => Err(Violation { line: 0, message: "empty trace".to_owned() }),
+    }
+}
@@ -0,0 +1,196 @@
+//! Configuration hot-reload.
+//!
+//! [`spawn`] starts a task that reloads [`AppConfig`] on SIGHUP, or when a file in the config
+//! directory is added, removed or modified (polled every 2s). The new configuration replaces the
+//! one in the [`ConfigHandle`] only if it loads, [`AppConfig::problems`] reports no errors and
+//! [`AppConfig::restart_required`] is empty; otherwise the running configuration stays and the
+//! reason is logged.
+//!
+//! Everything read per request follows a reload: `cookie_ttl_seconds` (session cookies and store
+//! entries written from then on), `log_level`, `log_filters`, `features`, `contact.recipients`,
+//! `csrf.*`, `server.limits` and `admin_token`. Environment variables cannot change under a
+//! running process, so in practice reloads pick up edits to the config files.
+//!
+//! Setting, replacing or removing `admin_token` changes who may use the admin routes, so an
+//! applied reload that does it is recorded in the audit log (`privilege_changed`).
+
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use actix_web::web;
+use tokio::{
+    signal::unix::{Signal, SignalKind, signal},
+    task::JoinHandle,
+};
+use tracing::{info, warn};
+
+use crate::audit::{self, AuditAction, AuditEvent, AuditSink};
+use crate::config::{AppConfig, ConfigHandle, Severity};
+use crate::telemetry;
+
+/// How often the config directory is checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Start watching for SIGHUP and config file changes. Abort the returned task to stop.
+pub fn spawn(handle: web::Data<ConfigHandle>, audit: web::Data<dyn AuditSink>) -> JoinHandle<()> {
+    actix_web::rt::spawn(watch(handle, audit))
+}
+
+async fn watch(handle: web::Data<ConfigHandle>, audit: web::Data<dyn AuditSink>) {
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(stream) => Some(stream),
+        Err(e) => {
+            warn!("Config reload on SIGHUP unavailable: {e}");
+            None
+        }
+    };
+    let dir = AppConfig::config_dir();
+    let mut seen = fingerprint(&dir);
+    let mut ticker = tokio::time::interval(POLL_INTERVAL);
+
+    loop {
+        let trigger = tokio::select! {
+            _ = recv(&mut hangup) => "SIGHUP",
+            _ = ticker.tick() => {
+                let now = fingerprint(&dir);
+                if now == seen {
+                    continue;
+                }
+                seen = now;
+                "config file change"
+            }
+        };
+        reload(&handle, audit.get_ref(), trigger).await;
+    }
+}
+
+/// Next SIGHUP; never resolves without a signal stream.
+async fn recv(hangup: &mut Option<Signal>) {
+    match hangup {
+        Some(stream) => {
+            stream.recv().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Load, validate and apply a new configuration. Returns whether it was applied.
+pub async fn reload(handle: &ConfigHandle, audit: &dyn AuditSink, trigger: &str) -> bool {
+    // Loading reads files (config, `*_FILE` secrets), so it runs on the blocking pool.
+    let new = match web::block(AppConfig::load).await {
+        Ok(Ok(cfg)) => cfg,
+        Ok(Err(e)) => {
+            warn!("Config reload ({trigger}) rejected: {e}");
+            return false;
+        }
+        Err(e) => {
+            warn!("Config reload ({trigger}) failed: {e}");
+            return false;
+        }
+    };
+    apply(handle, audit, trigger, new).await
+}
+
+/// Replace the configuration in `handle` with `new`, unless it has errors or changes a setting
+/// that needs a restart. Returns whether it was applied.
+async fn apply(
+    handle: &ConfigHandle,
+    audit: &dyn AuditSink,
+    trigger: &str,
+    new: AppConfig,
+) -> bool {
+    let (errors, warnings): (Vec<_>, Vec<_>) =
+        new.problems().into_iter().partition(|p| p.severity == Severity::Error);
+    if !errors.is_empty() {
+        let list: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        warn!("Config reload ({trigger}) rejected: {}", list.join("; "));
+        return false;
+    }
+
+    let old = handle.load();
+    let restart = old.restart_required(&new);
+    if !restart.is_empty() {
+        warn!(
+            "Config reload ({trigger}) rejected: {} changed, which needs a restart",
+            restart.join(", ")
+        );
+        return false;
+    }
+
+    for warning in &warnings {
+        warn!("Config warning ({} mode): {warning}", new.mode);
+    }
+    let admin_token = match (&old.admin_token, &new.admin_token) {
+        (None, Some(_)) => Some("set"),
+        (Some(_), None) => Some("removed"),
+        (Some(old), Some(new)) if old != new => Some("replaced"),
+        _ => None,
+    };
+    telemetry::reload(&new);
+    handle.store(new);
+    info!("Config reloaded ({trigger})");
+    if let Some(change) = admin_token {
+        let event = AuditEvent::new(AuditAction::PrivilegeChanged)
+            .detail(format!("admin_token {change} by a config reload ({trigger})"));
+        audit::write(audit, event).await;
+    }
+    true
+}
+
+/// Name, modification time and size of every file directly in `dir` (where `AppConfig::load`
+/// looks), in name order. Empty if the directory is missing.
+fn fingerprint(dir: &Path) -> Vec<(PathBuf, Option<SystemTime>, u64)> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<_> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let meta = entry.metadata().ok().filter(|m| m.is_file())?;
+            Some((entry.path(), meta.modified().ok(), meta.len()))
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::audit::{AuditQuery, FileAuditSink};
+
+    /// A dev configuration (the generated secret key is an error in prod) with `values` set.
+    fn config(mut values: serde_json::Value) -> AppConfig {
+        values["mode"] = json!("dev");
+        serde_json::from_value(values).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn reloads_with_errors_or_restart_only_changes_are_rejected() {
+        let path = std::env::temp_dir().join(format!("reload-audit-{}.ndjson", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let audit = FileAuditSink::open(path.clone()).unwrap();
+        let handle = ConfigHandle::new(config(json!({ "cookie_ttl_seconds": 600 })));
+
+        let invalid = config(json!({ "contact": { "recipients": ["nobody"] } }));
+        assert!(!apply(&handle, &audit, "test", invalid).await, "a config with errors");
+        let restart = config(json!({ "cookie_ttl_seconds": 900, "port": 8080 }));
+        assert!(!apply(&handle, &audit, "test", restart).await, "a port change");
+        assert_eq!(handle.load().cookie_ttl_seconds, 600, "the running config stays");
+
+        let valid = config(json!({ "cookie_ttl_seconds": 900, "admin_token": "t" }));
+        assert!(apply(&handle, &audit, "test", valid).await);
+        assert_eq!(handle.load().cookie_ttl_seconds, 900);
+        assert_eq!(handle.load().admin_token.as_deref(), Some("t"));
+
+        let events = audit.query(&AuditQuery::default()).await.unwrap();
+        let details: Vec<_> = events.iter().map(|e| (e.action, e.detail.as_deref())).collect();
+        let detail = "admin_token set by a config reload (test)";
+        assert_eq!(details, [(AuditAction::PrivilegeChanged, Some(detail))]);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,227 @@
+//! Secret key rotation for the session cookie.
+//!
+//! `SessionMiddleware` only knows one key. [`Keyring`] keeps the current key plus the previous
+//! ones, and two middlewares bridge the gap:
+//! - [`upgrade_cookie`] (outside `SessionMiddleware`) re-seals a session cookie that only an old
+//!   key can open under the current key, by rewriting the request's `Cookie` header;
+//! - [`reissue`] (inside `SessionMiddleware`) renews such sessions so the response carries a
+//!   cookie sealed with the current key.
+
+use actix_session::SessionExt;
+use actix_web::{
+    HttpMessage,
+    body::MessageBody,
+    cookie::{Cookie, CookieJar, Key},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{COOKIE, HeaderValue},
+    middleware::Next,
+    web,
+};
+
+/// Cookie keys: the first one seals, all of them open.
+#[derive(Clone)]
+pub struct Keyring {
+    current: Key,
+    previous: Vec<Key>,
+}
+
+impl Keyring {
+    /// Build a keyring from keys in priority order. Returns `None` for an empty list.
+    pub fn new(keys: Vec<Key>) -> Option<Self> {
+        let mut keys = keys.into_iter();
+        let current = keys.next()?;
+        Some(Keyring { current, previous: keys.collect() })
+    }
+
+    /// The key `SessionMiddleware` seals cookies with.
+    pub fn current(&self) -> &Key {
+        &self.current
+    }
+
+    /// Number of verify-only keys.
+    pub fn previous_len(&self) -> usize {
+        self.previous.len()
+    }
+
+    /// If `cookie` (as sent by the client) opens only with a previous key, return it re-sealed
+    /// with the current key.
+    fn reseal(&self, cookie: Cookie<'static>) -> Option<Cookie<'static>> {
+        let jar = CookieJar::new();
+        if jar.private(&self.current).decrypt(cookie.clone()).is_some() {
+            return None;
+        }
+        let plain =
+            self.previous.iter().find_map(|key| jar.private(key).decrypt(cookie.clone()))?;
+
+        let mut jar = CookieJar::new();
+        jar.private_mut(&self.current).add(plain);
+        jar.get(cookie.name()).cloned()
+    }
+}
+
+/// Request extension: the session cookie was sealed with a previous key.
+#[derive(Clone, Copy)]
+struct SealedWithPreviousKey;
+
+/// Middleware: re-seal a session cookie sealed with a previous key, so `SessionMiddleware` can
+/// open it. Must wrap `SessionMiddleware` (i.e. be registered after it with `.wrap`).
+pub async fn upgrade_cookie(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let upgraded = match (
+        req.app_data::<web::Data<Keyring>>(),
+        req.app_data::<web::Data<crate::config::ConfigHandle>>().map(|handle| handle.load()),
+    ) {
+        (Some(keyring), Some(cfg)) if keyring.previous_len() > 0 => {
+            rewrite_cookie_header(&req, &cfg.cookie_name, |cookie| keyring.reseal(cookie))
+        }
+        _ => None,
+    };
+
+    if let Some(header) = upgraded {
+        // `req.cookies()` must not have been called yet: it caches the parsed header.
+        req.headers_mut().insert(COOKIE, header);
+        req.extensions_mut().insert(SealedWithPreviousKey);
+    }
+
+    next.call(req).await
+}
+
+/// Middleware: renew sessions whose cookie was sealed with a previous key, so the response
+/// re-issues it under the current key. Must be wrapped directly inside `SessionMiddleware`.
+pub async fn reissue(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    if req.extensions().get::<SealedWithPreviousKey>().is_some() {
+        req.get_session().renew();
+    }
+    next.call(req).await
+}
+
+/// Rebuild the `Cookie` header with the cookie `name` replaced by `f(cookie)`; `None` if `f`
+/// declined or the cookie is absent.
+fn rewrite_cookie_header(
+    req: &ServiceRequest,
+    name: &str,
+    f: impl Fn(Cookie<'static>) -> Option<Cookie<'static>>,
+) -> Option<HeaderValue> {
+    let mut changed = false;
+    let mut pairs = Vec::new();
+    for header in req.headers().get_all(COOKIE) {
+        for pair in header.to_str().ok()?.split(';').map(str::trim).filter(|p| !p.is_empty()) {
+            let replaced = match Cookie::parse_encoded(pair.to_owned()) {
+                Ok(cookie) if !changed && cookie.name() == name => f(cookie),
+                _ => None,
+            };
+            match replaced {
+                Some(cookie) => {
+                    changed = true;
+                    pairs.push(cookie.encoded().to_string());
+                }
+                None => pairs.push(pair.to_owned()),
+            }
+        }
+    }
+
+    if !changed {
+        return None;
+    }
+    HeaderValue::from_str(&pairs.join("; ")).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_session::{Session, SessionMiddleware, storage::CookieSessionStore};
+    use actix_web::{App, HttpResponse, middleware::from_fn, test};
+
+    use super::*;
+    use crate::config::{AppConfig, ConfigHandle};
+
+    /// One request to an app whose cookie keys are `keys`, with the middlewares ordered as in
+    /// `main`. Returns the session's `user` and the session cookie the response sets, if any.
+    async fn send(keys: &[Key], req: test::TestRequest) -> (String, Option<Cookie<'static>>) {
+        let cfg: AppConfig = serde_json::from_value(serde_json::json!({})).unwrap();
+        let cookie_name = cfg.cookie_name.clone();
+        let keyring = Keyring::new(keys.to_vec()).unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(ConfigHandle::new(cfg)))
+                .app_data(web::Data::new(keyring.clone()))
+                .route(
+                    "/login",
+                    web::post().to(|session: Session| async move {
+                        session.insert("user", "alice").unwrap();
+                        HttpResponse::Ok().finish()
+                    }),
+                )
+                .route(
+                    "/",
+                    web::get().to(|session: Session| async move {
+                        session.get::<String>("user").unwrap().unwrap_or_default()
+                    }),
+                )
+                .wrap(from_fn(reissue))
+                .wrap(
+                    SessionMiddleware::builder(
+                        CookieSessionStore::default(),
+                        keyring.current().clone(),
+                    )
+                    .cookie_name(cookie_name.clone())
+                    .build(),
+                )
+                .wrap(from_fn(upgrade_cookie)),
+        )
+        .await;
+        let res = test::call_service(&app, req.to_request()).await;
+        let cookie = res.response().cookies().find(|c| c.name() == cookie_name);
+        let cookie = cookie.map(|c| c.into_owned());
+        let user = String::from_utf8(test::read_body(res).await.to_vec()).unwrap();
+        (user, cookie)
+    }
+
+    /// A session cookie for `alice` sealed with `key`.
+    async fn login(key: &Key) -> Cookie<'static> {
+        let (_, cookie) =
+            send(std::slice::from_ref(key), test::TestRequest::post().uri("/login")).await;
+        cookie.expect("a session cookie")
+    }
+
+    fn visit(cookie: Cookie<'static>) -> test::TestRequest {
+        test::TestRequest::get().uri("/").cookie(cookie)
+    }
+
+    #[actix_web::test]
+    async fn a_cookie_sealed_with_a_retired_key_is_accepted_and_reissued() {
+        let (current, retired) = (Key::generate(), Key::generate());
+        let old = login(&retired).await;
+
+        let (user, reissued) = send(&[current.clone(), retired], visit(old)).await;
+        assert_eq!(user, "alice");
+        let reissued = reissued.expect("the session is re-issued");
+        let (user, _) = send(&[current], visit(reissued)).await;
+        assert_eq!(user, "alice", "the new cookie opens with the current key alone");
+    }
+
+    #[actix_web::test]
+    async fn a_cookie_sealed_with_an_unknown_key_is_rejected() {
+        let (current, retired, unknown) = (Key::generate(), Key::generate(), Key::generate());
+        let forged = login(&unknown).await;
+
+        let (user, _) = send(&[current, retired], visit(forged)).await;
+        assert_eq!(user, "", "the session does not open");
+    }
+
+    #[actix_web::test]
+    async fn a_cookie_sealed_with_the_current_key_passes_through() {
+        let (current, retired) = (Key::generate(), Key::generate());
+        let cookie = login(&current).await;
+        let keyring = Keyring::new(vec![current.clone(), retired.clone()]).unwrap();
+        assert!(keyring.reseal(cookie.clone()).is_none());
+
+        let (user, reissued) = send(&[current, retired], visit(cookie)).await;
+        assert_eq!(user, "alice");
+        assert!(reissued.is_none(), "no new cookie for an unchanged session");
+    }
+}
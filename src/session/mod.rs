@@ -0,0 +1,188 @@
+/*! Session storage and server-side revocation.
+
+Submodules:
+- `store`    -> session state backends (`cookie|redis|memory`) for `SessionMiddleware`
+- `registry` -> authoritative registry of live session IDs (`serverSessions` in the TLA+ model)
+- `keyring`  -> secret key rotation: cookies sealed with a previous key are re-issued
+
+The registry is what makes logout, expiry and admin revocation stick: every authenticated
+request passes through [`enforce_registry`], which drops the identity if its session ID is no
+longer registered to that user, even when the client replays a perfectly valid cookie. That
+needs a registry that lives as long as the sessions, so it is only enforced with the `redis`
+and `memory` stores; cookie sessions outlive the process and its in-process registry.
+*/
+
+use std::sync::Arc;
+
+use actix_identity::IdentityExt;
+use actix_session::{SessionExt, SessionStatus};
+use actix_web::{
+    body::MessageBody,
+    cookie::time::Duration,
+    dev::{ServiceRequest, ServiceResponse},
+    error::{ErrorInternalServerError, ErrorServiceUnavailable},
+    middleware::Next,
+    web,
+};
+use tracing::{info, warn};
+
+use crate::audit::{self, AuditAction, AuditEvent};
+use crate::auth::AuthUser;
+use crate::config::{ConfigHandle, SessionConfig, SessionStoreKind};
+use crate::model_trace;
+
+pub mod keyring;
+pub mod registry;
+pub mod store;
+
+pub use keyring::Keyring;
+pub use registry::{MemorySessionRegistry, RedisSessionRegistry, SessionRegistry};
+pub use store::{LiveTtl, SessionBackend};
+
+/// Session state key holding the registry ID of an authenticated session.
+pub const SESSION_ID_KEY: &str = "session_id";
+
+/// Build the session store and the matching registry from configuration.
+///
+/// With `store = redis` both share one connection pool and the registry lives in Redis, so
+/// revocation applies across instances. Otherwise the registry is in-process, and only enforced
+/// for the `memory` store (see [`SessionStoreKind::server_side`]).
+pub async fn from_config(
+    cfg: &SessionConfig,
+) -> anyhow::Result<(SessionBackend, web::Data<dyn SessionRegistry>)> {
+    let pool = match cfg.store {
+        SessionStoreKind::Redis => Some(redis_pool(cfg)?),
+        SessionStoreKind::Cookie | SessionStoreKind::Memory => None,
+    };
+
+    let backend = SessionBackend::from_config(cfg, pool.as_ref()).await?;
+    let registry: Arc<dyn SessionRegistry> = match pool {
+        Some(pool) => Arc::new(RedisSessionRegistry::new(pool, cfg.key_prefix.clone())),
+        None => Arc::new(MemorySessionRegistry::default()),
+    };
+
+    Ok((backend, web::Data::from(registry)))
+}
+
+/// Create the Redis connection pool described by `cfg`. Connections are established lazily.
+fn redis_pool(cfg: &SessionConfig) -> anyhow::Result<deadpool_redis::Pool> {
+    let mut redis_cfg = deadpool_redis::Config::from_url(cfg.redis_url.clone());
+    redis_cfg.pool = Some(deadpool_redis::PoolConfig::new(cfg.pool_size));
+    Ok(redis_cfg.create_pool(Some(deadpool_redis::Runtime::Tokio1))?)
+}
+
+/// Session configuration for the Redis at `APP_SESSION__REDIS_URL` (the default URL otherwise)
+/// under a prefix of its own, with its pool. Panics when no server answers: the Redis tests are
+/// `#[ignore]`d and only run on request (`cargo test -- --ignored`).
+#[cfg(test)]
+pub(crate) async fn test_redis() -> (SessionConfig, deadpool_redis::Pool) {
+    let mut cfg = SessionConfig::default();
+    if let Ok(url) = std::env::var("APP_SESSION__REDIS_URL") {
+        cfg.redis_url = url;
+    }
+    cfg.key_prefix = format!("test:{}:", registry::generate_session_id());
+    let pool = redis_pool(&cfg).unwrap();
+    let registry = RedisSessionRegistry::new(pool.clone(), "");
+    match tokio::time::timeout(std::time::Duration::from_secs(1), registry.ping()).await {
+        Ok(Ok(())) => (cfg, pool),
+        _ => panic!("no Redis server at {}", cfg.redis_url),
+    }
+}
+
+/// Middleware that honours an identity only while its session is registered.
+///
+/// Must be wrapped inside `IdentityMiddleware` (i.e. registered before it with `.wrap`).
+///
+/// [TLA+ Action] SessionModel!AccessProtected
+/// Enforces the guard `serverSessions[c.s] = u`: if the session ID is missing, revoked, expired
+/// or owned by someone else, the session is purged and the request continues anonymously.
+///
+/// With the cookie store nothing is checked: a restart would otherwise log everyone out, and
+/// another instance would reject the cookie.
+///
+/// When the request writes a valid session, the store extends it to `cookie_ttl_seconds`; its
+/// registry entry is refreshed to match.
+pub async fn enforce_registry(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let enforced = req
+        .app_data::<web::Data<ConfigHandle>>()
+        .is_some_and(|cfg| cfg.load().session.store.server_side());
+    let mut refresh = None;
+    if let Ok(identity) = req.get_identity()
+        && let Ok(user) = identity.id()
+        && let Some(registry) = req.app_data::<web::Data<dyn SessionRegistry>>().cloned()
+    {
+        let session_id = req.get_session().get::<String>(SESSION_ID_KEY).ok().flatten();
+        let owner = match &session_id {
+            Some(session_id) if enforced => {
+                registry.lookup(session_id).await.map_err(ErrorServiceUnavailable)?
+            }
+            _ => None,
+        };
+        if enforced && let Some(session_id) = &session_id {
+            model_trace::access(&user, session_id, owner.as_deref());
+        }
+
+        if enforced && owner.as_deref() != Some(user.as_str()) {
+            info!(user = %user, "Session revoked or expired; continuing anonymously");
+            identity.logout();
+            let mut event =
+                AuditEvent::from_request(AuditAction::SessionExpired, req.request()).user(user);
+            if let Some(session_id) = &session_id {
+                event = event.session(session_id);
+            }
+            audit::record(req.request(), event).await;
+        } else {
+            refresh = Some(registry);
+        }
+    }
+
+    let res = next.call(req).await?;
+
+    // A logout purges the session and a login registers its new ID with the full TTL.
+    let session = res.request().get_session();
+    if let Some(registry) = refresh
+        && session.status() == SessionStatus::Changed
+        && let Ok(Some(session_id)) = session.get::<String>(SESSION_ID_KEY)
+        && let Some(cfg) = res.request().app_data::<web::Data<ConfigHandle>>()
+    {
+        let ttl_seconds = cfg.load().cookie_ttl_seconds;
+        let ttl = std::time::Duration::from_secs(ttl_seconds);
+        if let Err(e) = registry.refresh(&session_id, ttl).await {
+            warn!("Cannot refresh the session registry TTL: {e}");
+        }
+        AuthUser::extend(&session, ttl_seconds).map_err(ErrorInternalServerError)?;
+    }
+    Ok(res)
+}
+
+/// Middleware that gives the session cookie the live `cookie_ttl_seconds` as its `Max-Age`.
+///
+/// `SessionMiddleware` stamps the TTL it was built with; this rewrites it after a configuration
+/// reload. Must wrap `SessionMiddleware` (i.e. be registered after it with `.wrap`).
+pub async fn apply_cookie_ttl(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let cfg = req.app_data::<web::Data<ConfigHandle>>().map(|handle| handle.load());
+    let mut res = next.call(req).await?;
+
+    let Some(cfg) = cfg else {
+        return Ok(res);
+    };
+    let ttl = cfg.cookie_ttl();
+    // Leave removal cookies (`Max-Age=0`) alone.
+    let stale = res.response().cookies().find(|c| {
+        c.name() == cfg.cookie_name
+            && c.max_age().is_some_and(|age| age > Duration::ZERO && age != ttl)
+    });
+    if let Some(cookie) = stale {
+        let mut cookie = cookie.into_owned();
+        cookie.set_max_age(ttl);
+        res.response_mut().del_cookie(&cfg.cookie_name);
+        res.response_mut().add_cookie(&cookie).map_err(ErrorInternalServerError)?;
+    }
+    Ok(res)
+}
@@ -0,0 +1,482 @@
+//! Authoritative server-side registry of live sessions.
+//!
+//! This is the `serverSessions` map of `specs/SessionModel.tla`: a session ID maps to the user
+//! that owns it, or is absent ("NULL"). The cookie alone is never enough; the revocation
+//! middleware checks every authenticated request against this registry, so a replayed cookie
+//! stops working as soon as its entry is revoked or expires.
+//!
+//! Entries expire like the session itself: registered with `cookie_ttl_seconds` at login and
+//! refreshed whenever the session store extends the session (on every write), so an active
+//! session is not cut off `cookie_ttl_seconds` after login.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use deadpool_redis::redis::AsyncCommands;
+use futures_util::future::BoxFuture;
+use rand::{Rng, distr::Alphanumeric};
+use tracing::{Instrument as _, debug};
+
+use crate::audit;
+use crate::metrics;
+
+/// How long a Redis per-user set outlives the newest session in it, so that
+/// [`SessionRegistry::sweep`] still finds (and counts) that session's ID after it expired.
+const USER_SET_GRACE: Duration = Duration::from_secs(10 * 60);
+
+/// Registry of live sessions (`serverSessions` in the TLA+ model).
+pub trait SessionRegistry: Send + Sync {
+    /// Record that `session_id` belongs to `user` for `ttl`.
+    fn register<'a>(
+        &'a self,
+        session_id: &'a str,
+        user: &'a str,
+        ttl: Duration,
+    ) -> BoxFuture<'a, anyhow::Result<()>>;
+
+    /// Extend a live session to `ttl` from now. Revoked or expired sessions stay gone.
+    fn refresh<'a>(
+        &'a self,
+        session_id: &'a str,
+        ttl: Duration,
+    ) -> BoxFuture<'a, anyhow::Result<()>>;
+
+    /// The owner of `session_id`, or `None` if it was never registered, was revoked or expired.
+    fn lookup<'a>(&'a self, session_id: &'a str) -> BoxFuture<'a, anyhow::Result<Option<String>>>;
+
+    /// The live session whose [`audit::session_hash`] is `session_hash`, as its ID and owner.
+    /// Scans the whole registry, so it is meant for the admin routes, not for every request.
+    fn find<'a>(
+        &'a self,
+        session_hash: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<Option<(String, String)>>>;
+
+    /// Invalidate a single session.
+    fn revoke<'a>(&'a self, session_id: &'a str) -> BoxFuture<'a, anyhow::Result<()>>;
+
+    /// Invalidate every session owned by `user`. Returns how many were revoked.
+    fn revoke_user<'a>(&'a self, user: &'a str) -> BoxFuture<'a, anyhow::Result<usize>>;
+
+    /// Drop sessions whose TTL ran out (counted in `sessions_expired_total`) and return how many
+    /// are live, for the `sessions_active` metric.
+    fn sweep(&self) -> BoxFuture<'_, anyhow::Result<usize>>;
+
+    /// Round trip to the backing store, for readiness checks. In-process registries are always
+    /// reachable.
+    fn ping(&self) -> BoxFuture<'_, anyhow::Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    /// Release server connections on shutdown, once no request can write sessions anymore.
+    fn close(&self) {}
+}
+
+/// Generate a fresh, unguessable session ID.
+///
+/// [TLA+ Invariant] Safety: Session Integrity
+/// 48 random alphanumeric characters (~285 bits) make a collision with a live ID, or with one
+/// still held by any client, practically impossible: Login always picks a "Fresh" ID.
+pub fn generate_session_id() -> String {
+    rand::rng().sample_iter(&Alphanumeric).take(48).map(char::from).collect()
+}
+
+/// In-process registry. Correct for a single instance; use [`RedisSessionRegistry`] when
+/// running more than one.
+#[derive(Clone, Default)]
+pub struct MemorySessionRegistry {
+    sessions: Arc<RwLock<HashMap<String, (String, Instant)>>>,
+}
+
+impl SessionRegistry for MemorySessionRegistry {
+    fn register<'a>(
+        &'a self,
+        session_id: &'a str,
+        user: &'a str,
+        ttl: Duration,
+    ) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let mut sessions = self.sessions.write().unwrap_or_else(|e| e.into_inner());
+            purge_expired(&mut sessions);
+            sessions.insert(session_id.to_owned(), (user.to_owned(), Instant::now() + ttl));
+            Ok(())
+        })
+    }
+
+    fn refresh<'a>(
+        &'a self,
+        session_id: &'a str,
+        ttl: Duration,
+    ) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let mut sessions = self.sessions.write().unwrap_or_else(|e| e.into_inner());
+            let now = Instant::now();
+            if let Some((_, expires)) = sessions.get_mut(session_id)
+                && *expires > now
+            {
+                *expires = now + ttl;
+            }
+            Ok(())
+        })
+    }
+
+    fn lookup<'a>(&'a self, session_id: &'a str) -> BoxFuture<'a, anyhow::Result<Option<String>>> {
+        Box::pin(async move {
+            let sessions = self.sessions.read().unwrap_or_else(|e| e.into_inner());
+            Ok(sessions
+                .get(session_id)
+                .filter(|(_, expires)| *expires > Instant::now())
+                .map(|(user, _)| user.clone()))
+        })
+    }
+
+    fn find<'a>(
+        &'a self,
+        session_hash: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<Option<(String, String)>>> {
+        Box::pin(async move {
+            let sessions = self.sessions.read().unwrap_or_else(|e| e.into_inner());
+            let now = Instant::now();
+            Ok(sessions
+                .iter()
+                .find(|(id, (_, expires))| {
+                    *expires > now && audit::session_hash(id) == session_hash
+                })
+                .map(|(id, (user, _))| (id.clone(), user.clone())))
+        })
+    }
+
+    fn revoke<'a>(&'a self, session_id: &'a str) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let mut sessions = self.sessions.write().unwrap_or_else(|e| e.into_inner());
+            sessions.remove(session_id);
+            Ok(())
+        })
+    }
+
+    fn revoke_user<'a>(&'a self, user: &'a str) -> BoxFuture<'a, anyhow::Result<usize>> {
+        Box::pin(async move {
+            let mut sessions = self.sessions.write().unwrap_or_else(|e| e.into_inner());
+            let before = sessions.len();
+            sessions.retain(|_, (owner, _)| owner != user);
+            Ok(before - sessions.len())
+        })
+    }
+
+    fn sweep(&self) -> BoxFuture<'_, anyhow::Result<usize>> {
+        Box::pin(async move {
+            let mut sessions = self.sessions.write().unwrap_or_else(|e| e.into_inner());
+            purge_expired(&mut sessions);
+            Ok(sessions.len())
+        })
+    }
+}
+
+/// Remove the expired entries of a [`MemorySessionRegistry`].
+fn purge_expired(sessions: &mut HashMap<String, (String, Instant)>) {
+    let now = Instant::now();
+    let before = sessions.len();
+    sessions.retain(|_, (_, expires)| *expires > now);
+    record_expired(before - sessions.len());
+}
+
+/// Count and log sessions dropped from a registry because their TTL ran out.
+fn record_expired(count: usize) {
+    if count > 0 {
+        debug!(count, "Expired sessions swept");
+    }
+    metrics::record_sessions_expired(count);
+}
+
+/// Run one Redis registry operation in a `session_registry` span.
+fn traced<'a, T>(
+    operation: &'static str,
+    op: impl Future<Output = T> + Send + 'a,
+) -> BoxFuture<'a, T> {
+    let span = tracing::info_span!(
+        "session_registry",
+        otel.name = %format_args!("session registry {operation}"),
+        otel.kind = "client",
+        operation,
+    );
+    Box::pin(op.instrument(span))
+}
+
+/// Redis-backed registry shared by every instance.
+///
+/// Layout (under the configured key prefix):
+/// - `{prefix}sid:{session_id}` -> user, with `EX` set to the session TTL
+/// - `{prefix}user:{user}`      -> set of that user's session IDs (for "revoke all")
+#[derive(Clone)]
+pub struct RedisSessionRegistry {
+    pool: deadpool_redis::Pool,
+    prefix: String,
+}
+
+impl RedisSessionRegistry {
+    pub fn new(pool: deadpool_redis::Pool, prefix: impl Into<String>) -> Self {
+        RedisSessionRegistry { pool, prefix: prefix.into() }
+    }
+
+    fn sid_key(&self, session_id: &str) -> String {
+        format!("{}sid:{session_id}", self.prefix)
+    }
+
+    fn user_key(&self, user: &str) -> String {
+        format!("{}user:{user}", self.prefix)
+    }
+
+    /// Every per-user set under the prefix.
+    async fn user_keys(
+        &self,
+        conn: &mut deadpool_redis::Connection,
+    ) -> anyhow::Result<Vec<String>> {
+        let mut keys = Vec::new();
+        let mut iter = conn.scan_match::<_, String>(self.user_key("*")).await?;
+        while let Some(key) = iter.next_item().await {
+            keys.push(key);
+        }
+        Ok(keys)
+    }
+}
+
+impl SessionRegistry for RedisSessionRegistry {
+    fn register<'a>(
+        &'a self,
+        session_id: &'a str,
+        user: &'a str,
+        ttl: Duration,
+    ) -> BoxFuture<'a, anyhow::Result<()>> {
+        traced("register", async move {
+            let mut conn = self.pool.get().await?;
+            let user_key = self.user_key(user);
+            deadpool_redis::redis::pipe()
+                .atomic()
+                .set_ex(self.sid_key(session_id), user, ttl.as_secs().max(1))
+                .sadd(&user_key, session_id)
+                .expire(
+                    &user_key,
+                    i64::try_from((ttl + USER_SET_GRACE).as_secs()).unwrap_or(i64::MAX),
+                )
+                .query_async::<()>(&mut conn)
+                .await?;
+            Ok(())
+        })
+    }
+
+    /// `EXPIRE` leaves a missing key missing, and `GET` reads the owner only if it is still
+    /// there, so the per-user set is extended along with it.
+    fn refresh<'a>(
+        &'a self,
+        session_id: &'a str,
+        ttl: Duration,
+    ) -> BoxFuture<'a, anyhow::Result<()>> {
+        traced("refresh", async move {
+            let mut conn = self.pool.get().await?;
+            let (extended, owner): (bool, Option<String>) = deadpool_redis::redis::pipe()
+                .atomic()
+                .expire(self.sid_key(session_id), i64::try_from(ttl.as_secs().max(1))?)
+                .get(self.sid_key(session_id))
+                .query_async(&mut conn)
+                .await?;
+            if let Some(owner) = owner.filter(|_| extended) {
+                let grace = i64::try_from((ttl + USER_SET_GRACE).as_secs()).unwrap_or(i64::MAX);
+                let _: () = conn.expire(self.user_key(&owner), grace).await?;
+            }
+            Ok(())
+        })
+    }
+
+    fn lookup<'a>(&'a self, session_id: &'a str) -> BoxFuture<'a, anyhow::Result<Option<String>>> {
+        traced("lookup", async move {
+            let mut conn = self.pool.get().await?;
+            Ok(conn.get(self.sid_key(session_id)).await?)
+        })
+    }
+
+    /// Looks through the per-user sets, which also hold expired IDs until the next sweep.
+    fn find<'a>(
+        &'a self,
+        session_hash: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<Option<(String, String)>>> {
+        traced("find", async move {
+            let mut conn = self.pool.get().await?;
+            let user_keys = self.user_keys(&mut conn).await?;
+            for user_key in user_keys {
+                let session_ids: Vec<String> = conn.smembers(&user_key).await?;
+                if let Some(session_id) =
+                    session_ids.into_iter().find(|id| audit::session_hash(id) == session_hash)
+                {
+                    let owner: Option<String> = conn.get(self.sid_key(&session_id)).await?;
+                    return Ok(owner.map(|owner| (session_id, owner)));
+                }
+            }
+            Ok(None)
+        })
+    }
+
+    fn revoke<'a>(&'a self, session_id: &'a str) -> BoxFuture<'a, anyhow::Result<()>> {
+        traced("revoke", async move {
+            let mut conn = self.pool.get().await?;
+            let owner: Option<String> = conn.get_del(self.sid_key(session_id)).await?;
+            if let Some(owner) = owner {
+                let _: () = conn.srem(self.user_key(&owner), session_id).await?;
+            }
+            Ok(())
+        })
+    }
+
+    fn revoke_user<'a>(&'a self, user: &'a str) -> BoxFuture<'a, anyhow::Result<usize>> {
+        traced("revoke_user", async move {
+            let mut conn = self.pool.get().await?;
+            let user_key = self.user_key(user);
+            let session_ids: Vec<String> = conn.smembers(&user_key).await?;
+            let sid_keys: Vec<String> = session_ids.iter().map(|s| self.sid_key(s)).collect();
+            let revoked: usize = if sid_keys.is_empty() { 0 } else { conn.del(&sid_keys).await? };
+            let _: () = conn.del(&user_key).await?;
+            Ok(revoked)
+        })
+    }
+
+    /// Redis expires `sid:` keys on its own; their IDs linger in the per-user sets until this
+    /// removes them. `SREM` reports what it removed, so with several instances sweeping, each
+    /// expiry is counted once.
+    fn sweep(&self) -> BoxFuture<'_, anyhow::Result<usize>> {
+        traced("sweep", async move {
+            let mut conn = self.pool.get().await?;
+            let user_keys = self.user_keys(&mut conn).await?;
+
+            let (mut live, mut expired) = (0, 0);
+            for user_key in user_keys {
+                let session_ids: Vec<String> = conn.smembers(&user_key).await?;
+                let mut exists = deadpool_redis::redis::pipe();
+                for session_id in &session_ids {
+                    exists.exists(self.sid_key(session_id));
+                }
+                let exists: Vec<bool> = exists.query_async(&mut conn).await?;
+                let stale: Vec<&String> = session_ids
+                    .iter()
+                    .zip(exists)
+                    .filter_map(|(session_id, exists)| (!exists).then_some(session_id))
+                    .collect();
+                live += session_ids.len() - stale.len();
+                if !stale.is_empty() {
+                    let removed: usize = conn.srem(&user_key, &stale).await?;
+                    expired += removed;
+                }
+            }
+            record_expired(expired);
+            Ok(live)
+        })
+    }
+
+    /// `PING` over the pool shared with the session store.
+    fn ping(&self) -> BoxFuture<'_, anyhow::Result<()>> {
+        Box::pin(async move {
+            let mut conn = self.pool.get().await?;
+            deadpool_redis::redis::cmd("PING").query_async::<()>(&mut conn).await?;
+            Ok(())
+        })
+    }
+
+    /// Close the pool shared with the session store; in-flight commands have completed.
+    fn close(&self) {
+        self.pool.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TTL: Duration = Duration::from_secs(300);
+    const SHORT_TTL: Duration = Duration::from_millis(50);
+
+    /// Sessions `a1` and `a2` for alice and `b1` for bob.
+    async fn populate(registry: &dyn SessionRegistry) {
+        registry.register("a1", "alice", TTL).await.unwrap();
+        registry.register("a2", "alice", TTL).await.unwrap();
+        registry.register("b1", "bob", TTL).await.unwrap();
+    }
+
+    #[actix_web::test]
+    async fn revoke_user_revokes_only_that_users_sessions() {
+        let registry = MemorySessionRegistry::default();
+        populate(&registry).await;
+
+        assert_eq!(registry.revoke_user("alice").await.unwrap(), 2);
+        assert_eq!(registry.lookup("a1").await.unwrap(), None);
+        assert_eq!(registry.lookup("a2").await.unwrap(), None);
+        assert_eq!(registry.lookup("b1").await.unwrap().as_deref(), Some("bob"));
+        assert_eq!(registry.revoke_user("alice").await.unwrap(), 0);
+    }
+
+    #[actix_web::test]
+    async fn sweep_drops_expired_sessions_and_counts_live_ones() {
+        let registry = MemorySessionRegistry::default();
+        populate(&registry).await;
+        registry.register("c1", "carol", SHORT_TTL).await.unwrap();
+        assert_eq!(registry.sweep().await.unwrap(), 4);
+
+        tokio::time::sleep(SHORT_TTL * 2).await;
+        assert_eq!(registry.lookup("c1").await.unwrap(), None);
+        assert_eq!(registry.sweep().await.unwrap(), 3);
+        assert!(!registry.sessions.read().unwrap().contains_key("c1"));
+    }
+
+    #[actix_web::test]
+    async fn refresh_extends_only_live_sessions() {
+        let registry = MemorySessionRegistry::default();
+        registry.register("a1", "alice", SHORT_TTL).await.unwrap();
+        registry.register("a2", "alice", SHORT_TTL).await.unwrap();
+        registry.register("b1", "bob", TTL).await.unwrap();
+        registry.refresh("a1", TTL).await.unwrap();
+        registry.revoke("b1").await.unwrap();
+        registry.refresh("b1", TTL).await.unwrap();
+
+        tokio::time::sleep(SHORT_TTL * 2).await;
+        registry.refresh("a2", TTL).await.unwrap();
+        assert_eq!(registry.lookup("a1").await.unwrap().as_deref(), Some("alice"));
+        assert_eq!(registry.lookup("a2").await.unwrap(), None, "an expired session stays expired");
+        assert_eq!(registry.lookup("b1").await.unwrap(), None, "a revoked session stays revoked");
+    }
+
+    #[actix_web::test]
+    async fn find_resolves_the_hash_of_a_live_session() {
+        let registry = MemorySessionRegistry::default();
+        populate(&registry).await;
+        registry.register("c1", "carol", SHORT_TTL).await.unwrap();
+
+        let found = registry.find(&audit::session_hash("b1")).await.unwrap();
+        assert_eq!(found, Some(("b1".to_owned(), "bob".to_owned())));
+        assert_eq!(registry.find("b1").await.unwrap(), None, "the raw ID is not a hash");
+        tokio::time::sleep(SHORT_TTL * 2).await;
+        assert_eq!(registry.find(&audit::session_hash("c1")).await.unwrap(), None);
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a Redis server at APP_SESSION__REDIS_URL"]
+    async fn redis_registry_revokes_and_sweeps() {
+        let (cfg, pool) = crate::session::test_redis().await;
+        let registry = RedisSessionRegistry::new(pool, cfg.key_prefix);
+        populate(&registry).await;
+        // Redis expiries have a one second resolution.
+        registry.register("c1", "carol", Duration::from_secs(1)).await.unwrap();
+        assert_eq!(registry.sweep().await.unwrap(), 4);
+        registry.refresh("missing", TTL).await.unwrap();
+        assert_eq!(registry.lookup("missing").await.unwrap(), None);
+        let found = registry.find(&audit::session_hash("a2")).await.unwrap();
+        assert_eq!(found, Some(("a2".to_owned(), "alice".to_owned())));
+
+        assert_eq!(registry.revoke_user("alice").await.unwrap(), 2);
+        assert_eq!(registry.lookup("a1").await.unwrap(), None);
+        assert_eq!(registry.lookup("b1").await.unwrap().as_deref(), Some("bob"));
+
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert_eq!(registry.sweep().await.unwrap(), 1);
+        registry.revoke_user("bob").await.unwrap();
+        registry.revoke_user("carol").await.unwrap();
+    }
+}
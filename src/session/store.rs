@@ -0,0 +1,394 @@
+//! Session storage backends selected from `AppConfig.session`.
+//!
+//! `SessionMiddleware` is generic over its store, so the concrete backend is wrapped in
+//! [`SessionBackend`], which dispatches to:
+//! - `cookie`: `CookieSessionStore` (state inside the encrypted cookie, nothing on the server)
+//! - `redis`:  `RedisSessionStore` over a deadpool connection pool
+//! - `memory`: [`MemorySessionStore`], an in-process map for tests and single-node dev
+//!
+//! Redis and memory operations run in `session_store` spans (exported with `telemetry.otlp`).
+//!
+//! [`LiveTtl`] wraps the backend so entries are written with the current `cookie_ttl_seconds`
+//! rather than the TTL `SessionMiddleware` was built with.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::{Duration as StdDuration, Instant},
+};
+
+use actix_session::storage::{
+    CookieSessionStore, LoadError, RedisSessionStore, SaveError, SessionKey, SessionStore,
+    UpdateError,
+};
+use actix_web::{cookie::time::Duration, web};
+use rand::{Rng, distr::Alphanumeric};
+use tracing::{Instrument as _, Span};
+
+use crate::config::{ConfigHandle, SessionConfig, SessionStoreKind};
+
+type SessionState = HashMap<String, String>;
+
+/// Session store chosen at startup from configuration.
+///
+/// Cheap to clone: the Redis pool and the in-memory map are shared between clones, so every
+/// worker sees the same sessions.
+#[derive(Clone)]
+pub enum SessionBackend {
+    Cookie,
+    Redis(RedisSessionStore),
+    Memory(MemorySessionStore),
+}
+
+impl SessionBackend {
+    /// Build the backend described by `cfg`.
+    ///
+    /// `redis` must be the pool from [`super::redis_pool`] when `cfg.store` is `redis`.
+    pub async fn from_config(
+        cfg: &SessionConfig,
+        redis: Option<&deadpool_redis::Pool>,
+    ) -> anyhow::Result<Self> {
+        Ok(match (cfg.store, redis) {
+            (SessionStoreKind::Cookie, _) => SessionBackend::Cookie,
+            (SessionStoreKind::Memory, _) => SessionBackend::Memory(MemorySessionStore::default()),
+            (SessionStoreKind::Redis, None) => {
+                anyhow::bail!("session.store = redis requires a Redis pool")
+            }
+            (SessionStoreKind::Redis, Some(pool)) => {
+                let pool = pool.clone();
+                let prefix = cfg.key_prefix.clone();
+                let store = RedisSessionStore::builder_pooled(pool)
+                    .cache_keygen(move |key| format!("{prefix}{key}"))
+                    .build()
+                    .await?;
+                SessionBackend::Redis(store)
+            }
+        })
+    }
+
+    /// Span around one store operation. The cookie store does no I/O and gets none.
+    fn span(&self, operation: &'static str) -> Span {
+        match self {
+            SessionBackend::Cookie => Span::none(),
+            SessionBackend::Redis(_) | SessionBackend::Memory(_) => tracing::info_span!(
+                "session_store",
+                otel.name = %format_args!("session {operation}"),
+                otel.kind = "client",
+                store = %self.kind(),
+                operation,
+            ),
+        }
+    }
+
+    /// Which kind of backend this is.
+    pub fn kind(&self) -> SessionStoreKind {
+        match self {
+            SessionBackend::Cookie => SessionStoreKind::Cookie,
+            SessionBackend::Redis(_) => SessionStoreKind::Redis,
+            SessionBackend::Memory(_) => SessionStoreKind::Memory,
+        }
+    }
+}
+
+impl SessionStore for SessionBackend {
+    async fn load(&self, session_key: &SessionKey) -> Result<Option<SessionState>, LoadError> {
+        let load = async {
+            match self {
+                SessionBackend::Cookie => CookieSessionStore::default().load(session_key).await,
+                SessionBackend::Redis(store) => store.load(session_key).await,
+                SessionBackend::Memory(store) => store.load(session_key).await,
+            }
+        };
+        load.instrument(self.span("load")).await
+    }
+
+    async fn save(
+        &self,
+        session_state: SessionState,
+        ttl: &Duration,
+    ) -> Result<SessionKey, SaveError> {
+        let save = async {
+            match self {
+                SessionBackend::Cookie => {
+                    CookieSessionStore::default().save(session_state, ttl).await
+                }
+                SessionBackend::Redis(store) => store.save(session_state, ttl).await,
+                SessionBackend::Memory(store) => store.save(session_state, ttl).await,
+            }
+        };
+        save.instrument(self.span("save")).await
+    }
+
+    async fn update(
+        &self,
+        session_key: SessionKey,
+        session_state: SessionState,
+        ttl: &Duration,
+    ) -> Result<SessionKey, UpdateError> {
+        let update = async {
+            match self {
+                SessionBackend::Cookie => {
+                    CookieSessionStore::default().update(session_key, session_state, ttl).await
+                }
+                SessionBackend::Redis(store) => store.update(session_key, session_state, ttl).await,
+                SessionBackend::Memory(store) => {
+                    store.update(session_key, session_state, ttl).await
+                }
+            }
+        };
+        update.instrument(self.span("update")).await
+    }
+
+    async fn update_ttl(&self, session_key: &SessionKey, ttl: &Duration) -> anyhow::Result<()> {
+        let update_ttl = async {
+            match self {
+                SessionBackend::Cookie => {
+                    CookieSessionStore::default().update_ttl(session_key, ttl).await
+                }
+                SessionBackend::Redis(store) => store.update_ttl(session_key, ttl).await,
+                SessionBackend::Memory(store) => store.update_ttl(session_key, ttl).await,
+            }
+        };
+        update_ttl.instrument(self.span("update_ttl")).await
+    }
+
+    async fn delete(&self, session_key: &SessionKey) -> anyhow::Result<()> {
+        let delete = async {
+            match self {
+                SessionBackend::Cookie => CookieSessionStore::default().delete(session_key).await,
+                SessionBackend::Redis(store) => store.delete(session_key).await,
+                SessionBackend::Memory(store) => store.delete(session_key).await,
+            }
+        };
+        delete.instrument(self.span("delete")).await
+    }
+}
+
+/// [`SessionBackend`] that ignores the TTL passed by `SessionMiddleware` (fixed when the app is
+/// built) and uses the live `cookie_ttl_seconds`, so a configuration reload applies to every
+/// session written afterwards.
+#[derive(Clone)]
+pub struct LiveTtl {
+    backend: SessionBackend,
+    config: web::Data<ConfigHandle>,
+}
+
+impl LiveTtl {
+    pub fn new(backend: SessionBackend, config: web::Data<ConfigHandle>) -> Self {
+        LiveTtl { backend, config }
+    }
+
+    fn ttl(&self) -> Duration {
+        self.config.load().cookie_ttl()
+    }
+}
+
+impl SessionStore for LiveTtl {
+    async fn load(&self, session_key: &SessionKey) -> Result<Option<SessionState>, LoadError> {
+        self.backend.load(session_key).await
+    }
+
+    async fn save(
+        &self,
+        session_state: SessionState,
+        _ttl: &Duration,
+    ) -> Result<SessionKey, SaveError> {
+        self.backend.save(session_state, &self.ttl()).await
+    }
+
+    async fn update(
+        &self,
+        session_key: SessionKey,
+        session_state: SessionState,
+        _ttl: &Duration,
+    ) -> Result<SessionKey, UpdateError> {
+        self.backend.update(session_key, session_state, &self.ttl()).await
+    }
+
+    async fn update_ttl(&self, session_key: &SessionKey, _ttl: &Duration) -> anyhow::Result<()> {
+        self.backend.update_ttl(session_key, &self.ttl()).await
+    }
+
+    async fn delete(&self, session_key: &SessionKey) -> anyhow::Result<()> {
+        self.backend.delete(session_key).await
+    }
+}
+
+/// In-process session store.
+///
+/// Sessions are keyed by a random 64-character alphanumeric ID and expire after their TTL.
+/// Expired entries are dropped when read and swept whenever a new session is saved.
+#[derive(Clone, Default)]
+pub struct MemorySessionStore {
+    sessions: Arc<RwLock<HashMap<String, (SessionState, Instant)>>>,
+}
+
+impl MemorySessionStore {
+    fn deadline(ttl: &Duration) -> Instant {
+        let secs = u64::try_from(ttl.whole_seconds()).unwrap_or(0);
+        Instant::now() + StdDuration::from_secs(secs)
+    }
+
+    fn generate_key() -> String {
+        rand::rng().sample_iter(&Alphanumeric).take(64).map(char::from).collect()
+    }
+}
+
+impl SessionStore for MemorySessionStore {
+    async fn load(&self, session_key: &SessionKey) -> Result<Option<SessionState>, LoadError> {
+        let mut sessions = self.sessions.write().unwrap_or_else(|e| e.into_inner());
+        match sessions.get(session_key.as_ref()) {
+            Some((state, expires)) if *expires > Instant::now() => Ok(Some(state.clone())),
+            Some(_) => {
+                sessions.remove(session_key.as_ref());
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn save(
+        &self,
+        session_state: SessionState,
+        ttl: &Duration,
+    ) -> Result<SessionKey, SaveError> {
+        let key = Self::generate_key();
+        let now = Instant::now();
+        let mut sessions = self.sessions.write().unwrap_or_else(|e| e.into_inner());
+        sessions.retain(|_, (_, expires)| *expires > now);
+        sessions.insert(key.clone(), (session_state, Self::deadline(ttl)));
+        SessionKey::try_from(key).map_err(|e| SaveError::Other(e.into()))
+    }
+
+    async fn update(
+        &self,
+        session_key: SessionKey,
+        session_state: SessionState,
+        ttl: &Duration,
+    ) -> Result<SessionKey, UpdateError> {
+        {
+            let mut sessions = self.sessions.write().unwrap_or_else(|e| e.into_inner());
+            if let Some(entry) = sessions.get_mut(session_key.as_ref())
+                && entry.1 > Instant::now()
+            {
+                *entry = (session_state, Self::deadline(ttl));
+                return Ok(session_key);
+            }
+            sessions.remove(session_key.as_ref());
+        }
+
+        // The session vanished (expired or deleted) between load and update:
+        // persist it under a fresh key rather than resurrecting the old one.
+        self.save(session_state, ttl).await.map_err(|e| match e {
+            SaveError::Serialization(err) => UpdateError::Serialization(err),
+            SaveError::Other(err) => UpdateError::Other(err),
+        })
+    }
+
+    async fn update_ttl(&self, session_key: &SessionKey, ttl: &Duration) -> anyhow::Result<()> {
+        let mut sessions = self.sessions.write().unwrap_or_else(|e| e.into_inner());
+        if let Some((_, expires)) = sessions.get_mut(session_key.as_ref())
+            && *expires > Instant::now()
+        {
+            *expires = Self::deadline(ttl);
+        }
+        Ok(())
+    }
+
+    async fn delete(&self, session_key: &SessionKey) -> anyhow::Result<()> {
+        let mut sessions = self.sessions.write().unwrap_or_else(|e| e.into_inner());
+        sessions.remove(session_key.as_ref());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(value: &str) -> SessionState {
+        HashMap::from([("user".to_owned(), value.to_owned())])
+    }
+
+    const TTL: Duration = Duration::minutes(5);
+
+    /// `SessionKey` is not `Clone`.
+    fn copy(key: &SessionKey) -> SessionKey {
+        SessionKey::try_from(key.as_ref().to_owned()).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn memory_store_round_trips_state() {
+        let store = MemorySessionStore::default();
+        let key = store.save(state("alice"), &TTL).await.unwrap();
+        assert_eq!(key.as_ref().len(), 64);
+        assert_eq!(store.load(&key).await.unwrap(), Some(state("alice")));
+
+        let unknown = SessionKey::try_from("x".repeat(64)).unwrap();
+        assert_eq!(store.load(&unknown).await.unwrap(), None);
+    }
+
+    #[actix_web::test]
+    async fn memory_store_update_keeps_the_key() {
+        let store = MemorySessionStore::default();
+        let key = store.save(state("alice"), &TTL).await.unwrap();
+        let updated = store.update(copy(&key), state("bob"), &TTL).await.unwrap();
+        assert_eq!(updated.as_ref(), key.as_ref());
+        assert_eq!(store.load(&key).await.unwrap(), Some(state("bob")));
+    }
+
+    #[actix_web::test]
+    async fn memory_store_update_of_a_vanished_session_saves_a_new_one() {
+        let store = MemorySessionStore::default();
+        let key = store.save(state("alice"), &TTL).await.unwrap();
+        store.delete(&key).await.unwrap();
+
+        let updated = store.update(copy(&key), state("bob"), &TTL).await.unwrap();
+        assert_ne!(updated.as_ref(), key.as_ref());
+        assert_eq!(store.load(&key).await.unwrap(), None);
+        assert_eq!(store.load(&updated).await.unwrap(), Some(state("bob")));
+    }
+
+    #[actix_web::test]
+    async fn memory_store_expires_sessions_after_their_ttl() {
+        let store = MemorySessionStore::default();
+        let key = store.save(state("alice"), &Duration::ZERO).await.unwrap();
+        assert_eq!(store.load(&key).await.unwrap(), None);
+
+        let key = store.save(state("bob"), &Duration::ZERO).await.unwrap();
+        store.update_ttl(&key, &TTL).await.unwrap();
+        assert_eq!(store.load(&key).await.unwrap(), None, "an expired session stays expired");
+        let key = store.save(state("bob"), &Duration::ZERO).await.unwrap();
+        let updated = store.update(copy(&key), state("bob"), &TTL).await.unwrap();
+        assert_ne!(updated.as_ref(), key.as_ref(), "an update does not revive an expired key");
+
+        let key = store.save(state("carol"), &TTL).await.unwrap();
+        store.update_ttl(&key, &Duration::ZERO).await.unwrap();
+        assert_eq!(store.load(&key).await.unwrap(), None);
+    }
+
+    #[actix_web::test]
+    async fn memory_store_delete_removes_only_that_session() {
+        let store = MemorySessionStore::default();
+        let alice = store.save(state("alice"), &TTL).await.unwrap();
+        let bob = store.save(state("bob"), &TTL).await.unwrap();
+        store.delete(&alice).await.unwrap();
+        assert_eq!(store.load(&alice).await.unwrap(), None);
+        assert_eq!(store.load(&bob).await.unwrap(), Some(state("bob")));
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a Redis server at APP_SESSION__REDIS_URL"]
+    async fn redis_store_round_trips_state() {
+        let (mut cfg, pool) = crate::session::test_redis().await;
+        cfg.store = SessionStoreKind::Redis;
+        let store = SessionBackend::from_config(&cfg, Some(&pool)).await.unwrap();
+
+        let key = store.save(state("alice"), &TTL).await.unwrap();
+        assert_eq!(store.load(&key).await.unwrap(), Some(state("alice")));
+        let key = store.update(key, state("bob"), &TTL).await.unwrap();
+        assert_eq!(store.load(&key).await.unwrap(), Some(state("bob")));
+        store.delete(&key).await.unwrap();
+        assert_eq!(store.load(&key).await.unwrap(), None);
+    }
+}
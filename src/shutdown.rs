@@ -0,0 +1,167 @@
+//! Graceful shutdown.
+//!
+//! The HTTP servers run with actix's own signal handling disabled; [`signal`] waits for SIGTERM
+//! or SIGINT and [`Coordinator::run`] then tears everything down in a fixed order, logging each
+//! phase:
+//! 1. mark the instance not ready ([`Readiness`]);
+//! 2. stop accepting connections (listeners paused, queued connections wait in the backlog);
+//! 3. drain in-flight requests, up to `server.shutdown_timeout_seconds`, then close the
+//!    listeners and remove our Unix socket files;
+//! 4. stop background tasks (config watcher, certificate reloader);
+//! 5. terminate the SSR server;
+//! 6. flush sessions: every request has written its session by now, so the session store's
+//!    connections are closed;
+//! 7. flush logs (including the access log) and exported traces.
+//!
+//! While not ready, [`close_connections`] ends keep-alive connections after their current
+//! response, so clients reconnect elsewhere instead of holding the drain open. A second signal
+//! during the drain skips the rest of it: the remaining phases run and the process exits with
+//! whatever requests are still in flight.
+
+use std::{
+    io::Write as _,
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    Error,
+    body::MessageBody,
+    dev::{ServerHandle, ServiceRequest, ServiceResponse},
+    http::ConnectionType,
+    middleware::Next,
+    web,
+};
+use tokio::{
+    signal::unix::{SignalKind, signal as unix_signal},
+    task::JoinHandle,
+};
+use tracing::{error, info, warn};
+
+use crate::{access_log, listen, session::SessionRegistry, ssr::SsrSupervisor, telemetry};
+
+/// Number of phases in [`Coordinator::run`], for the log lines.
+const PHASES: u8 = 7;
+
+/// Whether this instance should receive traffic; false from the start of shutdown.
+#[derive(Debug)]
+pub struct Readiness(AtomicBool);
+
+impl Default for Readiness {
+    fn default() -> Self {
+        Readiness(AtomicBool::new(true))
+    }
+}
+
+impl Readiness {
+    pub fn is_ready(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+
+    fn set_not_ready(&self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+/// Middleware closing the connection after each response once shutdown has started.
+pub async fn close_connections(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let readiness = req.app_data::<web::Data<Readiness>>().cloned();
+    let mut res = next.call(req).await?;
+    if readiness.is_some_and(|readiness| !readiness.is_ready()) {
+        res.response_mut().head_mut().set_connection_type(ConnectionType::Close);
+    }
+    Ok(res)
+}
+
+/// Wait for SIGTERM or SIGINT and return its name.
+pub async fn signal() -> &'static str {
+    let (Ok(mut term), Ok(mut int)) =
+        (unix_signal(SignalKind::terminate()), unix_signal(SignalKind::interrupt()))
+    else {
+        error!("Cannot install SIGTERM/SIGINT handlers; graceful shutdown unavailable");
+        return std::future::pending().await;
+    };
+    tokio::select! {
+        _ = term.recv() => "SIGTERM",
+        _ = int.recv() => "SIGINT",
+    }
+}
+
+/// Everything torn down on shutdown, in the order of the module docs.
+pub struct Coordinator {
+    pub readiness: web::Data<Readiness>,
+    /// The app server first, then the plain-HTTP redirect and metrics servers if any.
+    pub servers: Vec<ServerHandle>,
+    pub shutdown_timeout: Duration,
+    pub socket_files: Vec<PathBuf>,
+    /// Background tasks with a name for the log.
+    pub tasks: Vec<(&'static str, JoinHandle<()>)>,
+    pub ssr: SsrSupervisor,
+    pub sessions: web::Data<dyn SessionRegistry>,
+}
+
+impl Coordinator {
+    /// Run every phase after `trigger` (the signal name). Returns whether the drain completed;
+    /// if not, the servers are still stopping and must not be awaited.
+    pub async fn run(self, trigger: &str) -> bool {
+        let started = Instant::now();
+        let phase = |n: u8, what: &str| info!("Shutdown ({trigger}) {n}/{PHASES}: {what}");
+
+        self.readiness.set_not_ready();
+        phase(1, "marked not ready");
+
+        for server in &self.servers {
+            server.pause().await;
+        }
+        phase(2, "stopped accepting connections");
+
+        phase(
+            3,
+            &format!("draining in-flight requests (up to {}s)", self.shutdown_timeout.as_secs()),
+        );
+        let drain_started = Instant::now();
+        let graceful = futures_util::future::join_all(self.servers.iter().map(|s| s.stop(true)));
+        // actix handles one stop command at a time, so a forced stop cannot overtake this one.
+        let drained = tokio::select! {
+            _ = graceful => {
+                info!("Drained in {} ms", drain_started.elapsed().as_millis());
+                true
+            }
+            second = signal() => {
+                warn!("{second} during drain; abandoning the remaining requests");
+                false
+            }
+        };
+        listen::cleanup(&self.socket_files);
+
+        let names: Vec<&str> = self.tasks.iter().map(|(name, _)| *name).collect();
+        for (_, task) in self.tasks {
+            task.abort();
+        }
+        phase(4, &format!("stopped background tasks ({})", names.join(", ")));
+
+        self.ssr.shutdown().await;
+        phase(5, "stopped SSR server");
+
+        self.sessions.close();
+        phase(6, "flushed sessions");
+
+        phase(
+            7,
+            &format!("flushing logs and traces; shut down in {} ms", started.elapsed().as_millis()),
+        );
+        // Writing queued access log records and sending the last span batch both block.
+        let _ = tokio::task::spawn_blocking(|| {
+            access_log::shutdown();
+            telemetry::shutdown();
+        })
+        .await;
+        let _ = std::io::stdout().flush();
+        let _ = std::io::stderr().flush();
+        drained
+    }
+}
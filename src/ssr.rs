@@ -0,0 +1,356 @@
+//! Supervised Node SSR server.
+//!
+//! [`SsrSupervisor`] owns the `node <ssr.entry> --port <ssr.port>` child process instead of the
+//! fire-and-forget `Inertia::start_node_server`:
+//! - it probes the server's `GET /health` endpoint and restarts the child with exponential
+//!   backoff when it exits or stops answering; with `ssr.process = external` it only probes;
+//! - its status is served by `GET /health/ssr` (see `handlers::health`);
+//! - [`fallback`] renders pages client-side (through an SSR-less Inertia instance) while the
+//!   server is not healthy, so requests never wait on a dead SSR endpoint;
+//! - [`SsrSupervisor::shutdown`] stops the child once the HTTP server has drained (see
+//!   `shutdown.rs`). The child runs in its own process group so a terminal Ctrl-C does not kill
+//!   it behind the supervisor's back, and it is killed on drop as a last resort.
+
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use actix_web::{
+    Error,
+    body::MessageBody,
+    dev::{Extensions, ServiceRequest, ServiceResponse},
+    middleware::Next,
+    web,
+};
+use inertia_rust::Inertia;
+use reqwest::Url;
+use serde::Serialize;
+use tokio::{process::Child, sync::watch, task::JoinHandle};
+use tracing::{info, warn};
+
+use crate::config::{SsrConfig, SsrProcess};
+
+/// Interval between health probes.
+const PROBE_INTERVAL: Duration = Duration::from_secs(2);
+/// Consecutive failed probes after which the child is restarted.
+const MAX_PROBE_FAILURES: u32 = 3;
+/// First restart delay; doubled after every failed start, reset once healthy.
+const MIN_BACKOFF: Duration = Duration::from_millis(500);
+/// Upper bound for the restart delay.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// Time the child gets to exit after `GET /shutdown` before it is killed.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+
+/// Lifecycle state of the SSR server.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SsrState {
+    /// SSR is off (dev/test mode, Vite dev server, or no bundle); pages render client-side.
+    Disabled,
+    /// The child was spawned and has not answered a health probe yet.
+    Starting,
+    /// The last health probe succeeded.
+    Healthy,
+    /// The server exited or failed its health probes; a restart is pending (spawned) or the
+    /// external server is unreachable.
+    Unhealthy,
+    /// The supervisor was shut down.
+    Stopped,
+}
+
+impl std::fmt::Display for SsrState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            SsrState::Disabled => "disabled",
+            SsrState::Starting => "starting",
+            SsrState::Healthy => "healthy",
+            SsrState::Unhealthy => "unhealthy",
+            SsrState::Stopped => "stopped",
+        };
+        f.write_str(s)
+    }
+}
+
+/// Snapshot of the supervisor state, served by `GET /health/ssr`.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SsrStatus {
+    pub state: SsrState,
+    /// PID of the running child, if any.
+    pub pid: Option<u32>,
+    /// Number of restarts since startup.
+    pub restarts: u32,
+    /// Why SSR is disabled, or the last start/health failure.
+    pub last_error: Option<String>,
+    /// Unix timestamp (seconds) of the last successful health probe.
+    pub last_healthy_at: Option<i64>,
+}
+
+impl SsrStatus {
+    fn new(state: SsrState) -> Self {
+        SsrStatus { state, pid: None, restarts: 0, last_error: None, last_healthy_at: None }
+    }
+}
+
+struct Inner {
+    status: RwLock<SsrStatus>,
+    /// SSR-less Inertia instance used while the SSR server is not healthy.
+    fallback: web::Data<Inertia>,
+    shutdown: watch::Sender<bool>,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+/// Owns and monitors the Node SSR child process. Cheap to clone.
+#[derive(Clone)]
+pub struct SsrSupervisor {
+    inner: Arc<Inner>,
+}
+
+impl SsrSupervisor {
+    fn new(status: SsrStatus, fallback: web::Data<Inertia>) -> Self {
+        SsrSupervisor {
+            inner: Arc::new(Inner {
+                status: RwLock::new(status),
+                fallback,
+                shutdown: watch::channel(false).0,
+                task: Mutex::new(None),
+            }),
+        }
+    }
+
+    /// A supervisor that never starts a child; `reason` is reported by the health route.
+    pub fn disabled(reason: impl Into<String>, fallback: web::Data<Inertia>) -> Self {
+        let mut status = SsrStatus::new(SsrState::Disabled);
+        status.last_error = Some(reason.into());
+        Self::new(status, fallback)
+    }
+
+    /// Spawn `node <entry> --port <port>` and supervise it, or only watch `url` when
+    /// `cfg.process = external`.
+    ///
+    /// Must be called from within the actix/tokio runtime.
+    pub fn start(cfg: &SsrConfig, url: Url, fallback: web::Data<Inertia>) -> Self {
+        let supervisor = Self::new(SsrStatus::new(SsrState::Starting), fallback);
+        let client = reqwest::Client::builder()
+            .timeout(cfg.timeout())
+            .build()
+            .unwrap_or_else(|_| reqwest::Client::new());
+        let shutdown = supervisor.inner.shutdown.subscribe();
+        let handle = match cfg.process {
+            SsrProcess::Spawn => actix_web::rt::spawn(supervise(
+                supervisor.clone(),
+                cfg.entry.clone(),
+                url,
+                client,
+                shutdown,
+            )),
+            SsrProcess::External => {
+                actix_web::rt::spawn(watch_external(supervisor.clone(), url, client, shutdown))
+            }
+        };
+        *supervisor.inner.task.lock().unwrap_or_else(|e| e.into_inner()) = Some(handle);
+        supervisor
+    }
+
+    /// Current status snapshot.
+    pub fn status(&self) -> SsrStatus {
+        self.inner.status.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Whether requests should be rendered client-side instead of through the SSR server.
+    pub fn use_fallback(&self) -> bool {
+        !matches!(self.status().state, SsrState::Healthy | SsrState::Disabled)
+    }
+
+    /// Stop supervising and shut the child down (gracefully, then by force).
+    pub async fn shutdown(&self) {
+        let _ = self.inner.shutdown.send(true);
+        let task = self.inner.task.lock().unwrap_or_else(|e| e.into_inner()).take();
+        if let Some(task) = task {
+            let _ = task.await;
+        }
+    }
+
+    fn update(&self, f: impl FnOnce(&mut SsrStatus)) {
+        f(&mut self.inner.status.write().unwrap_or_else(|e| e.into_inner()));
+    }
+
+    /// Record a health-probe result; returns the failure reason, if any.
+    fn record_probe(&self, result: Result<(), String>) -> Option<String> {
+        match result {
+            Ok(()) => {
+                self.update(|s| {
+                    if s.state != SsrState::Healthy {
+                        info!("SSR server healthy");
+                    }
+                    s.state = SsrState::Healthy;
+                    s.last_healthy_at = Some(chrono::Utc::now().timestamp());
+                });
+                None
+            }
+            Err(e) => {
+                let reason = format!("SSR health check failed: {e}");
+                self.update(|s| {
+                    if s.state == SsrState::Healthy {
+                        warn!("{reason}");
+                        s.state = SsrState::Unhealthy;
+                    }
+                    s.last_error = Some(reason.clone());
+                });
+                Some(reason)
+            }
+        }
+    }
+}
+
+/// Supervision loop: spawn, probe, restart with backoff until shutdown is requested.
+async fn supervise(
+    supervisor: SsrSupervisor,
+    entry: PathBuf,
+    url: Url,
+    client: reqwest::Client,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let mut backoff = MIN_BACKOFF;
+
+    loop {
+        let failure = match spawn_node(&entry, &url) {
+            Err(e) => format!("failed to launch SSR server at {}: {e}", entry.display()),
+            Ok(mut child) => {
+                info!("SSR server started (pid {}) on {url}", child.id().unwrap_or_default());
+                supervisor.update(|s| {
+                    s.state = SsrState::Starting;
+                    s.pid = child.id();
+                });
+
+                let mut failures = 0;
+                loop {
+                    tokio::select! {
+                        _ = shutdown.changed() => {
+                            stop_node(&client, &url, child).await;
+                            supervisor.update(|s| {
+                                s.state = SsrState::Stopped;
+                                s.pid = None;
+                            });
+                            return;
+                        }
+                        status = child.wait() => {
+                            break match status {
+                                Ok(status) => format!("SSR server exited ({status})"),
+                                Err(e) => format!("SSR server wait failed: {e}"),
+                            };
+                        }
+                        _ = tokio::time::sleep(PROBE_INTERVAL) => {
+                            match supervisor.record_probe(probe(&client, &url).await) {
+                                None => {
+                                    failures = 0;
+                                    backoff = MIN_BACKOFF;
+                                }
+                                Some(reason) => {
+                                    failures += 1;
+                                    if failures >= MAX_PROBE_FAILURES {
+                                        let _ = child.kill().await;
+                                        break format!("{reason} ({failures} times in a row)");
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        };
+
+        warn!("{failure}; restarting SSR server in {backoff:?}");
+        supervisor.update(|s| {
+            s.state = SsrState::Unhealthy;
+            s.pid = None;
+            s.last_error = Some(failure);
+        });
+
+        tokio::select! {
+            _ = shutdown.changed() => {
+                supervisor.update(|s| s.state = SsrState::Stopped);
+                return;
+            }
+            _ = tokio::time::sleep(backoff) => {}
+        }
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+        supervisor.update(|s| s.restarts += 1);
+    }
+}
+
+/// Watch loop for an external SSR server: probe only, its owner handles restarts.
+async fn watch_external(
+    supervisor: SsrSupervisor,
+    url: Url,
+    client: reqwest::Client,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    info!("Watching external SSR server at {url}");
+    loop {
+        if supervisor.record_probe(probe(&client, &url).await).is_some() {
+            // No boot phase to wait for: an unreachable external server is unhealthy.
+            supervisor.update(|s| s.state = SsrState::Unhealthy);
+        }
+        tokio::select! {
+            _ = shutdown.changed() => {
+                supervisor.update(|s| s.state = SsrState::Stopped);
+                return;
+            }
+            _ = tokio::time::sleep(PROBE_INTERVAL) => {}
+        }
+    }
+}
+
+fn spawn_node(entry: &PathBuf, url: &Url) -> std::io::Result<Child> {
+    if !entry.exists() {
+        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, "bundle not found"));
+    }
+    let mut cmd = tokio::process::Command::new("node");
+    cmd.arg(entry)
+        .arg("--port")
+        .arg(url.port_or_known_default().unwrap_or_default().to_string())
+        .stdin(Stdio::null())
+        .kill_on_drop(true);
+    #[cfg(unix)]
+    cmd.process_group(0);
+    cmd.spawn()
+}
+
+async fn probe(client: &reqwest::Client, url: &Url) -> Result<(), String> {
+    let health = url.join("health").map_err(|e| e.to_string())?;
+    let res = client.get(health).send().await.map_err(|e| e.to_string())?;
+    if res.status().is_success() { Ok(()) } else { Err(format!("status {}", res.status())) }
+}
+
+/// Ask the server to exit via `GET /shutdown`, then kill it if it is still running.
+async fn stop_node(client: &reqwest::Client, url: &Url, mut child: Child) {
+    info!("Stopping SSR server...");
+    if let Ok(shutdown) = url.join("shutdown") {
+        let _ = client.get(shutdown).send().await;
+    }
+    if tokio::time::timeout(SHUTDOWN_GRACE, child.wait()).await.is_err() {
+        let _ = child.kill().await;
+    }
+}
+
+/// Middleware: while SSR is unhealthy, resolve `web::Data<Inertia>` to the SSR-less instance for
+/// this request, so `Inertia::render` returns a client-rendered page immediately.
+pub async fn fallback(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let fallback = req
+        .app_data::<web::Data<SsrSupervisor>>()
+        .filter(|ssr| ssr.use_fallback())
+        .map(|ssr| ssr.inner.fallback.clone());
+    if let Some(inertia) = fallback {
+        // Data containers added later take precedence over the app-level `web::Data<Inertia>`.
+        let mut data = Extensions::new();
+        data.insert(inertia);
+        req.add_data_container(Rc::new(data));
+    }
+    next.call(req).await
+}
@@ -0,0 +1,323 @@
+//! Structured logging with `tracing`, per-request correlation IDs and optional OTLP trace
+//! export.
+//!
+//! [`init`] installs the subscriber once the configuration is loaded. It writes to stdout as JSON
+//! lines or pretty, multi-line text ([`AppConfig::log_format`]: json in `prod`, pretty in
+//! `dev`/`test`). Records from crates that use `log` (actix-web, inertia-rust, ...) go through
+//! it too. The filter is built from, in increasing precedence:
+//! - `log_level` for every module (default: `warn`, and `info` for this crate);
+//! - `log_filters`, one level per module path;
+//! - `RUST_LOG` directives, if set.
+//!
+//! [`reload`] rebuilds it when the configuration is reloaded.
+//!
+//! With `telemetry.otlp` set, spans of this crate at `info` and above (whatever the log filter
+//! says) are also exported over OTLP/HTTP in batches: `request` spans, `ssr_render` spans (see
+//! `metrics::inertia`) and the session store and registry spans (see `session`). [`shutdown`]
+//! flushes the last batch.
+//!
+//! [`request_span`] runs each request inside a `request` span carrying a request ID: the
+//! client's `X-Request-Id` when it is well-formed, a generated one otherwise. The ID is echoed
+//! in the `X-Request-Id` response header (error responses included) and stored in the request
+//! extensions ([`RequestId`]). The SSR server receives it in the page props (`requestId`, see
+//! [`shared_request_id`]), and every event logged while the request is handled carries it:
+//! session events, SSR failures reported by Inertia, ...
+//!
+//! When exporting, the span also joins the trace of an incoming W3C `traceparent` header, and
+//! the trace context goes back out in the `traceparent` response header and to the SSR server as
+//! the `traceparent` page prop (see [`shared_traceparent`]).
+
+use std::{collections::HashMap, fmt, io::IsTerminal as _, sync::OnceLock, time::Duration};
+
+use actix_web::{
+    Error, HttpMessage as _, HttpRequest, HttpResponse, ResponseError,
+    body::{BoxBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::{
+        StatusCode,
+        header::{HeaderMap, HeaderName, HeaderValue},
+    },
+    middleware::Next,
+};
+use opentelemetry::{
+    KeyValue, global,
+    propagation::Extractor,
+    trace::{TraceContextExt as _, TracerProvider as _},
+};
+use opentelemetry_otlp::{ExporterBuildError, WithExportConfig as _, WithHttpConfig as _};
+use opentelemetry_sdk::{
+    Resource,
+    propagation::TraceContextPropagator,
+    trace::{Sampler, SdkTracerProvider},
+};
+use tracing::{Instrument as _, Level, Span, field::Empty};
+use tracing_opentelemetry::OpenTelemetrySpanExt as _;
+use tracing_subscriber::{
+    EnvFilter, Layer as _, Registry, filter::Targets, layer::SubscriberExt as _, reload::Handle,
+};
+
+use crate::config::{AppConfig, LogFormat, OtlpConfig};
+
+/// Filter applied when `log_level` is unset.
+const DEFAULT_FILTER: &str = "warn,hello_actix=info";
+
+/// Request and response header carrying the request ID.
+pub const X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// W3C trace context header.
+pub const TRACEPARENT: HeaderName = HeaderName::from_static("traceparent");
+
+/// Longest client-supplied request ID that is accepted.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Swaps the filter on [`reload`].
+static FILTER: OnceLock<Handle<EnvFilter, Registry>> = OnceLock::new();
+
+/// The OTLP exporter's provider, flushed by [`shutdown`].
+static PROVIDER: OnceLock<SdkTracerProvider> = OnceLock::new();
+
+/// Install the subscriber for `cfg`. Call once, right after the configuration is loaded.
+pub fn init(cfg: &AppConfig) {
+    let (filter, handle) = tracing_subscriber::reload::Layer::new(filter(cfg));
+    let _ = FILTER.set(handle);
+
+    let fmt = tracing_subscriber::fmt::layer().with_ansi(std::io::stdout().is_terminal());
+    let output = match cfg.log_format() {
+        LogFormat::Json => fmt.json().with_current_span(true).with_span_list(false).boxed(),
+        LogFormat::Pretty => fmt.pretty().boxed(),
+    };
+
+    let provider = cfg.telemetry.otlp.as_ref().and_then(|otlp| {
+        tracer_provider(otlp)
+            .inspect_err(|e| eprintln!("Cannot export traces to {}: {e}", otlp.endpoint))
+            .ok()
+    });
+    let export = provider.map(|provider| {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+        let _ = PROVIDER.set(provider);
+        // Independent of the log filter, so quieting the logs does not break traces.
+        tracing_opentelemetry::layer()
+            .with_tracer(tracer)
+            .with_filter(Targets::new().with_target(env!("CARGO_CRATE_NAME"), Level::INFO))
+    });
+
+    let subscriber = tracing_subscriber::registry().with(output.with_filter(filter)).with(export);
+
+    if let Err(e) = tracing::subscriber::set_global_default(subscriber) {
+        eprintln!("Cannot install the log subscriber: {e}");
+        return;
+    }
+    // Let every `log` record through; the filter above decides.
+    if let Err(e) = tracing_log::LogTracer::init() {
+        eprintln!("Cannot forward `log` records: {e}");
+    }
+}
+
+/// Apply the `log_level` and `log_filters` of a reloaded configuration.
+pub fn reload(cfg: &AppConfig) {
+    if let Some(handle) = FILTER.get()
+        && let Err(e) = handle.reload(filter(cfg))
+    {
+        tracing::error!("Cannot apply the new log filter: {e}");
+    }
+}
+
+/// Flush and stop the trace exporter, if any. Blocks until the last batch is sent or the
+/// export times out.
+pub fn shutdown() {
+    if let Some(provider) = PROVIDER.get()
+        && let Err(e) = provider.shutdown()
+    {
+        tracing::warn!("Cannot flush traces: {e}");
+    }
+}
+
+fn tracer_provider(otlp: &OtlpConfig) -> Result<SdkTracerProvider, ExporterBuildError> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_endpoint(&otlp.endpoint)
+        .with_headers(otlp.headers.clone().into_iter().collect())
+        .with_timeout(Duration::from_millis(otlp.timeout_ms))
+        .build()?;
+    let resource = Resource::builder()
+        .with_service_name(otlp.service_name.clone())
+        .with_attribute(KeyValue::new("service.version", env!("CARGO_PKG_VERSION")))
+        .build();
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(otlp.sample_ratio))))
+        .with_resource(resource)
+        .build())
+}
+
+fn filter(cfg: &AppConfig) -> EnvFilter {
+    let mut directives = match cfg.log_level {
+        Some(level) => level.to_string(),
+        None => DEFAULT_FILTER.to_owned(),
+    };
+    for (module, level) in &cfg.log_filters {
+        directives.push_str(&format!(",{module}={level}"));
+    }
+    if let Ok(env) = std::env::var(EnvFilter::DEFAULT_ENV)
+        && !env.is_empty()
+    {
+        directives.push(',');
+        directives.push_str(&env);
+    }
+    // Later directives for the same module replace earlier ones.
+    EnvFilter::builder().parse_lossy(directives)
+}
+
+/// ID of the current request, in the request extensions.
+#[derive(Clone, Debug)]
+pub struct RequestId(pub String);
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl RequestId {
+    /// The client's ID if it is 1 to 128 visible ASCII characters, otherwise a fresh one.
+    fn from_header(value: Option<&HeaderValue>) -> Self {
+        let presented = value.and_then(|v| v.to_str().ok()).filter(|id| {
+            (1..=MAX_REQUEST_ID_LEN).contains(&id.len()) && id.bytes().all(|b| b.is_ascii_graphic())
+        });
+        match presented {
+            Some(id) => RequestId(id.to_owned()),
+            None => RequestId(format!("{:032x}", rand::random::<u128>())),
+        }
+    }
+}
+
+/// W3C `traceparent` of the current request's span, in the request extensions when traces
+/// are exported.
+#[derive(Clone, Debug)]
+pub struct TraceParent(pub String);
+
+/// Middleware running the request in a `request` span with its [`RequestId`]. Wrap it
+/// outermost, so every other middleware logs inside the span.
+pub async fn request_span(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let id = RequestId::from_header(req.headers().get(X_REQUEST_ID));
+    req.extensions_mut().insert(id.clone());
+    let method = req.method().clone();
+    let span = tracing::info_span!(
+        "request",
+        otel.kind = "server",
+        otel.status_code = Empty,
+        request_id = %id,
+        method = %method,
+        path = %req.path(),
+        route = Empty,
+        status = Empty,
+    );
+    // Without an exporter the propagator is a no-op: nothing is extracted nor injected.
+    let parent = global::get_text_map_propagator(|p| p.extract(&HeaderExtractor(req.headers())));
+    let _ = span.set_parent(parent);
+
+    let mut headers = Vec::new();
+    if let Ok(value) = HeaderValue::from_str(&id.0) {
+        headers.push((X_REQUEST_ID, value));
+    }
+    if let Some(traceparent) = traceparent(&span) {
+        if let Ok(value) = HeaderValue::from_str(&traceparent) {
+            headers.push((TRACEPARENT, value));
+        }
+        req.extensions_mut().insert(TraceParent(traceparent));
+    }
+
+    let res = next.call(req).instrument(span.clone()).await;
+    let (route, status) = match &res {
+        Ok(res) => (res.request().match_pattern().filter(|r| !r.is_empty()), res.status()),
+        Err(error) => (None, error.as_response_error().status_code()),
+    };
+    // Exported as `GET /admin/sessions/{session_hash}`, or just `GET` outside any route. The span's
+    // context already exists (for `traceparent`), so an `otel.name` field would be ignored.
+    let name = match &route {
+        Some(route) => format!("{method} {route}"),
+        None => method.to_string(),
+    };
+    span.context().span().update_name(name);
+    if let Some(route) = route {
+        span.record("route", route);
+    }
+    span.record("status", status.as_u16());
+    if status.is_server_error() {
+        span.record("otel.status_code", "error");
+    }
+
+    match res {
+        Ok(mut res) => {
+            for (name, value) in headers {
+                res.headers_mut().insert(name, value);
+            }
+            Ok(res.map_into_boxed_body())
+        }
+        // Errors from inner middleware (e.g. `413` from `limits::apply`) become responses later,
+        // outside any middleware; carry the headers along so they are sent there too.
+        Err(error) => Err(WithHeaders { error, headers }.into()),
+    }
+}
+
+/// `traceparent` for `span`, if traces are exported.
+fn traceparent(span: &Span) -> Option<String> {
+    let mut carrier = HashMap::new();
+    global::get_text_map_propagator(|p| p.inject_context(&span.context(), &mut carrier));
+    carrier.remove(TRACEPARENT.as_str())
+}
+
+/// Reads the trace context from request headers.
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|v| v.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(HeaderName::as_str).collect()
+    }
+}
+
+/// An error response with the request's correlation headers added.
+#[derive(Debug)]
+struct WithHeaders {
+    error: Error,
+    headers: Vec<(HeaderName, HeaderValue)>,
+}
+
+impl fmt::Display for WithHeaders {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl ResponseError for WithHeaders {
+    fn status_code(&self) -> StatusCode {
+        self.error.as_response_error().status_code()
+    }
+
+    fn error_response(&self) -> HttpResponse<BoxBody> {
+        let mut res = self.error.error_response();
+        for (name, value) in &self.headers {
+            res.headers_mut().insert(name.clone(), value.clone());
+        }
+        res
+    }
+}
+
+/// The `requestId` shared prop, also sent to the SSR server with the page.
+pub fn shared_request_id(req: &HttpRequest) -> Option<String> {
+    req.extensions().get::<RequestId>().map(|id| id.0.clone())
+}
+
+/// The `traceparent` shared prop, also sent to the SSR server with the page. `None` unless
+/// traces are exported.
+pub fn shared_traceparent(req: &HttpRequest) -> Option<String> {
+    req.extensions().get::<TraceParent>().map(|t| t.0.clone())
+}
@@ -0,0 +1,188 @@
+//! TLS termination with rustls (`AppConfig::tls`).
+//!
+//! [`server_config`] builds the rustls `ServerConfig` used for every TCP listener; actix adds the
+//! `h2` and `http/1.1` ALPN protocols, so clients negotiate HTTP/2. The certificate is served
+//! through [`CertResolver`], which [`spawn_reloader`] refreshes when the certificate or key file
+//! changes on disk (e.g. after certbot renews it). A bad pair is logged and the previous one
+//! kept. With `tls.client_ca`, clients must present a certificate issued by one of those CAs.
+//!
+//! [`redirect`] answers the optional plain-HTTP listener (`tls.redirect_http`) with a `301` to
+//! the same path over HTTPS.
+
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use actix_web::{HttpRequest, HttpResponse, http::header, web};
+use arc_swap::ArcSwap;
+use rustls::{
+    RootCertStore, ServerConfig,
+    crypto::CryptoProvider,
+    pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+    server::{ClientHello, ResolvesServerCert, WebPkiClientVerifier},
+    sign::CertifiedKey,
+};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::config::{ConfigHandle, TlsConfig};
+
+/// How often the certificate and key files are checked for changes.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Serves the current certificate; swapped in place by [`CertResolver::reload`].
+pub struct CertResolver {
+    current: ArcSwap<CertifiedKey>,
+    cert: PathBuf,
+    key: PathBuf,
+    provider: Arc<CryptoProvider>,
+}
+
+impl fmt::Debug for CertResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CertResolver").field("cert", &self.cert).field("key", &self.key).finish()
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.load_full())
+    }
+}
+
+impl CertResolver {
+    fn new(cert: &Path, key: &Path, provider: Arc<CryptoProvider>) -> io::Result<Self> {
+        let current = load_certified_key(cert, key, &provider)?;
+        Ok(CertResolver {
+            current: ArcSwap::from_pointee(current),
+            cert: cert.to_owned(),
+            key: key.to_owned(),
+            provider,
+        })
+    }
+
+    /// Re-read the certificate and key. On error the current pair stays in use.
+    pub fn reload(&self) -> io::Result<()> {
+        let certified = load_certified_key(&self.cert, &self.key, &self.provider)?;
+        self.current.store(Arc::new(certified));
+        Ok(())
+    }
+}
+
+/// Build the rustls configuration for `cfg`, with its certificate resolver.
+pub fn server_config(cfg: &TlsConfig) -> io::Result<(ServerConfig, Arc<CertResolver>)> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let resolver = Arc::new(CertResolver::new(&cfg.cert, &cfg.key, provider.clone())?);
+
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(io::Error::other)?;
+    let builder = match &cfg.client_ca {
+        Some(path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in read_certs(path)? {
+                roots.add(cert).map_err(|e| invalid(path, e))?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .build()
+                .map_err(|e| invalid(path, e))?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    Ok((builder.with_cert_resolver(resolver.clone()), resolver))
+}
+
+/// Reload the certificate whenever its files change. Abort the returned task to stop.
+pub fn spawn_reloader(resolver: Arc<CertResolver>) -> JoinHandle<()> {
+    actix_web::rt::spawn(async move {
+        let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        let stamp =
+            |r: &CertResolver| -> [Option<SystemTime>; 2] { [modified(&r.cert), modified(&r.key)] };
+        let mut seen = stamp(&resolver);
+        let mut ticker = tokio::time::interval(RELOAD_INTERVAL);
+        loop {
+            ticker.tick().await;
+            let now = stamp(&resolver);
+            if now == seen {
+                continue;
+            }
+            seen = now;
+            match resolver.reload() {
+                Ok(()) => info!("TLS certificate reloaded from {}", resolver.cert.display()),
+                Err(e) => warn!("TLS certificate reload failed, keeping the current one: {e}"),
+            }
+        }
+    })
+}
+
+/// Port the HTTPS listener actually bound, for [`redirect`] (`443` is left out of URLs).
+#[derive(Clone, Copy, Debug)]
+pub struct HttpsPort(pub u16);
+
+/// Default service of the plain-HTTP listener: `301` to the same path and query over HTTPS.
+///
+/// The target is `public_url` when it is set to an `https://` URL, otherwise the request's host on
+/// the HTTPS port.
+pub async fn redirect(
+    req: HttpRequest,
+    cfg: web::Data<ConfigHandle>,
+    https_port: web::Data<HttpsPort>,
+) -> HttpResponse {
+    let cfg = cfg.load();
+    let base = match cfg.public_url.as_deref() {
+        Some(url) if url.starts_with("https://") => url.trim_end_matches('/').to_owned(),
+        _ => {
+            let host = strip_port(req.connection_info().host());
+            match https_port.0 {
+                443 => format!("https://{host}"),
+                port => format!("https://{host}:{port}"),
+            }
+        }
+    };
+    let path = req.uri().path_and_query().map_or("/", |p| p.as_str());
+    HttpResponse::MovedPermanently()
+        .insert_header((header::LOCATION, format!("{base}{path}")))
+        .finish()
+}
+
+/// `example.com:80` -> `example.com`, `[::1]:80` -> `[::1]`.
+fn strip_port(host: &str) -> String {
+    match host.rsplit_once(':') {
+        Some((name, port)) if port.bytes().all(|b| b.is_ascii_digit()) => name.to_owned(),
+        _ => host.to_owned(),
+    }
+}
+
+fn load_certified_key(
+    cert: &Path,
+    key: &Path,
+    provider: &CryptoProvider,
+) -> io::Result<CertifiedKey> {
+    let chain = read_certs(cert)?;
+    let key_der = PrivateKeyDer::from_pem_file(key).map_err(|e| invalid(key, e))?;
+    let signing_key =
+        provider.key_provider.load_private_key(key_der).map_err(|e| invalid(key, e))?;
+    let certified = CertifiedKey::new(chain, signing_key);
+    // Catch a key that doesn't belong to the certificate before serving it.
+    certified.keys_match().map_err(|e| invalid(key, e))?;
+    Ok(certified)
+}
+
+fn read_certs(path: &Path) -> io::Result<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(Iterator::collect::<Result<Vec<_>, _>>)
+        .map_err(|e| invalid(path, e))?;
+    if certs.is_empty() {
+        return Err(invalid(path, "no certificates found"));
+    }
+    Ok(certs)
+}
+
+fn invalid(path: &Path, e: impl fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{}: {e}", path.display()))
+}
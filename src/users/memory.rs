@@ -0,0 +1,36 @@
+use std::collections::HashMap;
+
+use futures_util::future::BoxFuture;
+
+use super::{UserRecord, UserStore};
+use crate::config::UserAccount;
+
+/// Accounts held in memory, typically from `users.accounts` in the config.
+#[derive(Clone, Default)]
+pub struct MemoryUserStore {
+    users: HashMap<String, UserRecord>,
+}
+
+impl MemoryUserStore {
+    pub fn from_accounts(accounts: &[UserAccount]) -> Self {
+        let users = accounts
+            .iter()
+            .map(|account| {
+                let record = UserRecord {
+                    username: account.username.clone(),
+                    password_hash: account.password_hash.clone(),
+                    display_name: account.display_name.clone(),
+                    roles: account.roles.clone(),
+                };
+                (account.username.clone(), record)
+            })
+            .collect();
+        MemoryUserStore { users }
+    }
+}
+
+impl UserStore for MemoryUserStore {
+    fn find<'a>(&'a self, username: &'a str) -> BoxFuture<'a, anyhow::Result<Option<UserRecord>>> {
+        Box::pin(async move { Ok(self.users.get(username).cloned()) })
+    }
+}
@@ -0,0 +1,177 @@
+/*! User accounts and credential verification.
+
+Submodules:
+- `memory`    -> accounts from `users.accounts` in the config
+- `toml_file` -> accounts from a TOML file (`[[users]]` tables)
+- `sqlite`    -> accounts from a SQLite `users` table
+
+Every backend stores Argon2id password hashes in PHC string format (`hello-actix hash-password`
+prints one); plaintext passwords never leave [`verify_credentials`].
+*/
+
+use std::sync::{Arc, LazyLock};
+
+use argon2::{
+    Algorithm, Argon2, PasswordHash, PasswordHasher, PasswordVerifier, password_hash::SaltString,
+};
+use futures_util::future::BoxFuture;
+use tracing::warn;
+
+use crate::config::{UserStoreKind, UsersConfig};
+
+pub mod memory;
+pub mod sqlite;
+pub mod toml_file;
+
+pub use memory::MemoryUserStore;
+pub use sqlite::SqliteUserStore;
+pub use toml_file::TomlUserStore;
+
+/// A stored user account.
+#[derive(Clone, Debug)]
+pub struct UserRecord {
+    pub username: String,
+    /// Argon2id hash in PHC string format.
+    pub password_hash: String,
+    /// Name shown in the UI; falls back to the username.
+    pub display_name: Option<String>,
+    pub roles: Vec<String>,
+}
+
+impl UserRecord {
+    /// The display name, or the username if none is set.
+    pub fn name(&self) -> &str {
+        self.display_name.as_deref().unwrap_or(&self.username)
+    }
+}
+
+/// Lookup of user accounts by username.
+pub trait UserStore: Send + Sync {
+    /// The account named `username`, if any.
+    fn find<'a>(&'a self, username: &'a str) -> BoxFuture<'a, anyhow::Result<Option<UserRecord>>>;
+
+    /// Round trip to the backing store, for readiness checks. Stores loaded into memory at
+    /// startup are always reachable.
+    fn ping(&self) -> BoxFuture<'_, anyhow::Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Hash checked for unknown users, so they cost as much as a wrong password. It uses the
+/// parameters of [`hash_password`], which produces account hashes.
+static DUMMY_HASH: LazyLock<String> =
+    LazyLock::new(|| hash_password("dummy").expect("cannot hash the dummy password"));
+
+/// Build the user store selected by `cfg.store`.
+pub fn from_config(cfg: &UsersConfig) -> anyhow::Result<Arc<dyn UserStore>> {
+    // Hash the dummy password now: a failure stops startup instead of the first login.
+    LazyLock::force(&DUMMY_HASH);
+
+    let path = || {
+        cfg.path.clone().ok_or_else(|| {
+            anyhow::anyhow!("users.path is required for users.store = {}", cfg.store)
+        })
+    };
+
+    Ok(match cfg.store {
+        UserStoreKind::Memory => Arc::new(MemoryUserStore::from_accounts(&cfg.accounts)),
+        UserStoreKind::Toml => Arc::new(TomlUserStore::open(path()?)?),
+        UserStoreKind::Sqlite => Arc::new(SqliteUserStore::open(path()?)?),
+    })
+}
+
+/// Hash a password with Argon2id and a random salt, in PHC string format.
+pub fn hash_password(password: &str) -> anyhow::Result<String> {
+    let salt = SaltString::encode_b64(&rand::random::<[u8; 16]>())
+        .map_err(|e| anyhow::anyhow!("failed to encode salt: {e}"))?;
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| anyhow::anyhow!("failed to hash password: {e}"))
+}
+
+/// Check `password` against the stored account for `username`.
+///
+/// Returns the account on success and `None` for an unknown user, a wrong password or a stored
+/// hash that is not an Argon2id PHC string. Those accounts are checked against a dummy hash
+/// instead, so every failure takes the same time. The Argon2 work runs on the blocking thread
+/// pool.
+pub async fn verify_credentials(
+    store: &dyn UserStore,
+    username: &str,
+    password: &str,
+) -> anyhow::Result<Option<UserRecord>> {
+    let record = store.find(username).await?;
+    let hash = record.as_ref().map(|record| record.password_hash.clone());
+    let usable = hash.as_deref().is_some_and(is_argon2id);
+    if record.is_some() && !usable {
+        warn!(username, "Password hash is not an Argon2id PHC string; login refused");
+    }
+
+    let password = password.to_owned();
+    let matches = actix_web::web::block(move || {
+        let (hash, candidate) = match &hash {
+            Some(hash) if usable => (hash.as_str(), true),
+            _ => (DUMMY_HASH.as_str(), false),
+        };
+        let verified = PasswordHash::new(hash).is_ok_and(|parsed| {
+            Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok()
+        });
+        candidate && verified
+    })
+    .await?;
+
+    Ok(record.filter(|_| matches))
+}
+
+/// Whether `hash` is a PHC string for Argon2id (not Argon2i/Argon2d or another scheme).
+fn is_argon2id(hash: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|parsed| parsed.algorithm == Algorithm::Argon2id.ident())
+}
+
+#[cfg(test)]
+mod tests {
+    use argon2::{Params, Version};
+
+    use super::*;
+    use crate::config::UserAccount;
+
+    fn account(username: &str, password_hash: String) -> UserAccount {
+        UserAccount {
+            username: username.to_owned(),
+            password_hash,
+            display_name: None,
+            roles: vec![],
+        }
+    }
+
+    async fn login(store: &MemoryUserStore, username: &str, password: &str) -> Option<String> {
+        verify_credentials(store, username, password).await.unwrap().map(|record| record.username)
+    }
+
+    #[actix_web::test]
+    async fn verify_credentials_accepts_only_the_right_argon2id_password() {
+        let store =
+            MemoryUserStore::from_accounts(&[account("alice", hash_password("pw").unwrap())]);
+        assert_eq!(login(&store, "alice", "pw").await.as_deref(), Some("alice"));
+        assert_eq!(login(&store, "alice", "wrong").await, None);
+        assert_eq!(login(&store, "bob", "pw").await, None);
+        assert_eq!(login(&store, "bob", "dummy").await, None, "the dummy hash never matches");
+    }
+
+    #[actix_web::test]
+    async fn verify_credentials_refuses_other_argon2_variants() {
+        let salt = SaltString::encode_b64(b"argon2-variant").unwrap();
+        let accounts: Vec<UserAccount> =
+            [("argon2i", Algorithm::Argon2i), ("argon2d", Algorithm::Argon2d)]
+                .into_iter()
+                .map(|(username, algorithm)| {
+                    let argon2 = Argon2::new(algorithm, Version::V0x13, Params::default());
+                    account(username, argon2.hash_password(b"pw", &salt).unwrap().to_string())
+                })
+                .collect();
+        let store = MemoryUserStore::from_accounts(&accounts);
+        assert_eq!(login(&store, "argon2i", "pw").await, None);
+        assert_eq!(login(&store, "argon2d", "pw").await, None);
+    }
+}
@@ -0,0 +1,120 @@
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use futures_util::future::BoxFuture;
+use rusqlite::{Connection, OptionalExtension};
+
+use super::{UserRecord, UserStore};
+
+/// Accounts in a SQLite database. The table is created on first open:
+///
+/// ```sql
+/// CREATE TABLE users (
+///     username      TEXT PRIMARY KEY,
+///     password_hash TEXT NOT NULL,
+///     display_name  TEXT,
+///     roles         TEXT NOT NULL DEFAULT ''  -- comma-separated
+/// );
+/// ```
+#[derive(Clone)]
+pub struct SqliteUserStore {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteUserStore {
+    pub fn open(path: PathBuf) -> anyhow::Result<Self> {
+        let conn = Connection::open(&path)
+            .map_err(|e| anyhow::anyhow!("failed to open {}: {e}", path.display()))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS users (
+                username      TEXT PRIMARY KEY,
+                password_hash TEXT NOT NULL,
+                display_name  TEXT,
+                roles         TEXT NOT NULL DEFAULT ''
+            )",
+        )?;
+        Ok(SqliteUserStore { conn: Arc::new(Mutex::new(conn)) })
+    }
+}
+
+impl UserStore for SqliteUserStore {
+    fn find<'a>(&'a self, username: &'a str) -> BoxFuture<'a, anyhow::Result<Option<UserRecord>>> {
+        let conn = Arc::clone(&self.conn);
+        let username = username.to_owned();
+        Box::pin(async move {
+            actix_web::web::block(move || {
+                let conn = conn.lock().unwrap_or_else(|e| e.into_inner());
+                conn.query_row(
+                    "SELECT username, password_hash, display_name, roles
+                     FROM users WHERE username = ?1",
+                    [&username],
+                    |row| {
+                        let roles: String = row.get(3)?;
+                        Ok(UserRecord {
+                            username: row.get(0)?,
+                            password_hash: row.get(1)?,
+                            display_name: row.get(2)?,
+                            roles: roles
+                                .split(',')
+                                .map(str::trim)
+                                .filter(|r| !r.is_empty())
+                                .map(str::to_owned)
+                                .collect(),
+                        })
+                    },
+                )
+                .optional()
+                .map_err(anyhow::Error::from)
+            })
+            .await?
+        })
+    }
+
+    /// Query the `users` table, so a replaced or corrupted database file shows up.
+    fn ping(&self) -> BoxFuture<'_, anyhow::Result<()>> {
+        let conn = Arc::clone(&self.conn);
+        Box::pin(async move {
+            actix_web::web::block(move || {
+                let conn = conn.lock().unwrap_or_else(|e| e.into_inner());
+                conn.query_row("SELECT count(*) FROM users", [], |_| Ok(()))
+                    .map_err(anyhow::Error::from)
+            })
+            .await?
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn finds_accounts_with_their_display_name_and_roles() {
+        let path = std::env::temp_dir().join(format!("users-sqlite-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let store = SqliteUserStore::open(path.clone()).unwrap();
+        store.ping().await.unwrap();
+        store
+            .conn
+            .lock()
+            .unwrap()
+            .execute_batch(
+                "INSERT INTO users (username, password_hash, display_name, roles)
+                 VALUES ('alice', 'hash-a', 'Alice', 'admin, ops,,');
+                 INSERT INTO users (username, password_hash) VALUES ('bob', 'hash-b');",
+            )
+            .unwrap();
+
+        let reopened = SqliteUserStore::open(path.clone()).unwrap();
+        let alice = reopened.find("alice").await.unwrap().expect("alice");
+        assert_eq!(alice.password_hash, "hash-a");
+        assert_eq!(alice.name(), "Alice");
+        assert_eq!(alice.roles, ["admin", "ops"]);
+        let bob = reopened.find("bob").await.unwrap().expect("bob");
+        assert_eq!((bob.name(), bob.roles.len()), ("bob", 0));
+        assert!(reopened.find("carol").await.unwrap().is_none());
+        let _ = std::fs::remove_file(&path);
+    }
+}
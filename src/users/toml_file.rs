@@ -0,0 +1,83 @@
+use std::path::PathBuf;
+
+use futures_util::future::BoxFuture;
+use serde::Deserialize;
+
+use super::{MemoryUserStore, UserRecord, UserStore};
+use crate::config::UserAccount;
+
+/// Accounts read once at startup from a TOML file:
+///
+/// ```toml
+/// [[users]]
+/// username = "alice"
+/// password_hash = "$argon2id$v=19$m=19456,t=2,p=1$..."
+/// display_name = "Alice"   # optional
+/// roles = ["admin"]        # optional
+/// ```
+pub struct TomlUserStore {
+    inner: MemoryUserStore,
+}
+
+#[derive(Deserialize)]
+struct UsersFile {
+    #[serde(default)]
+    users: Vec<UserAccount>,
+}
+
+impl TomlUserStore {
+    pub fn open(path: PathBuf) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| anyhow::anyhow!("failed to read {}: {e}", path.display()))?;
+        let file: UsersFile = toml::from_str(&text)
+            .map_err(|e| anyhow::anyhow!("invalid users file {}: {e}", path.display()))?;
+        Ok(TomlUserStore { inner: MemoryUserStore::from_accounts(&file.users) })
+    }
+}
+
+impl UserStore for TomlUserStore {
+    fn find<'a>(&'a self, username: &'a str) -> BoxFuture<'a, anyhow::Result<Option<UserRecord>>> {
+        self.inner.find(username)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn users_file(name: &str, text: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("users-{name}-{}.toml", std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[actix_web::test]
+    async fn finds_accounts_from_users_tables() {
+        let path = users_file(
+            "accounts",
+            "[[users]]\nusername = \"alice\"\npassword_hash = \"hash-a\"\n\
+             display_name = \"Alice\"\nroles = [\"admin\"]\n\n\
+             [[users]]\nusername = \"bob\"\npassword_hash = \"hash-b\"\n",
+        );
+        let store = TomlUserStore::open(path.clone()).unwrap();
+        let alice = store.find("alice").await.unwrap().expect("alice");
+        assert_eq!((alice.password_hash.as_str(), alice.name()), ("hash-a", "Alice"));
+        assert_eq!(alice.roles, ["admin"]);
+        let bob = store.find("bob").await.unwrap().expect("bob");
+        assert_eq!((bob.name(), bob.roles.len()), ("bob", 0));
+        assert!(store.find("carol").await.unwrap().is_none());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn missing_or_invalid_files_are_errors() {
+        let missing = std::env::temp_dir().join("users-missing.toml");
+        let err = TomlUserStore::open(missing).err().expect("a missing file").to_string();
+        assert!(err.starts_with("failed to read"), "{err}");
+
+        let path = users_file("invalid", "[[users]]\nusername = \"alice\"\n");
+        let err = TomlUserStore::open(path.clone()).err().expect("no password_hash").to_string();
+        assert!(err.starts_with("invalid users file"), "{err}");
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,182 @@
+//! CSRF protection of state-changing requests, against the server binary.
+//!
+//! A page load issues the session's token (the `XSRF-TOKEN` cookie); a `POST /login` must send it
+//! back in the `X-XSRF-TOKEN` header or a `_token` form field, or get `419`. Only the `/admin/`
+//! routes, authenticated by the bearer admin token, skip the check.
+
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+
+use argon2::{Argon2, PasswordHasher as _, password_hash::SaltString};
+use reqwest::{RequestBuilder, StatusCode, header, redirect};
+use tokio::process::{Child, Command};
+
+const ADMIN_TOKEN: &str = "csrf-admin";
+const PAGE_EXPIRED: u16 = 419;
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("csrf-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Account `alice` with password `pw`, and an admin token.
+fn write_config(config_dir: &Path) {
+    let salt = SaltString::encode_b64(b"csrf-test-salt").unwrap();
+    let hash = Argon2::default().hash_password(b"pw", &salt).unwrap().to_string();
+    let config = format!(
+        "admin_token = \"{ADMIN_TOKEN}\"\n\n[[users.accounts]]\nusername = \"alice\"\n\
+         password_hash = \"{hash}\"\n"
+    );
+    std::fs::write(config_dir.join("local.toml"), config).unwrap();
+}
+
+fn start_server(config_dir: &Path, port_file: &Path) -> Child {
+    Command::new(env!("CARGO_BIN_EXE_hello-actix"))
+        .args(["serve", "--port-file"])
+        .arg(port_file)
+        .env_clear()
+        .env("PATH", std::env::var_os("PATH").unwrap_or_default())
+        .env("APP_CONFIG_DIR", config_dir)
+        .env("APP_MODE", "test")
+        .env("APP_PORT", "0")
+        .env("APP_SSR__ENABLED", "off")
+        .env("APP_SESSION__STORE", "memory")
+        .env("APP_METRICS__ENABLED", "false")
+        .env("APP_ACCESS_LOG__ENABLED", "false")
+        .env("APP_LOG_LEVEL", "error")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .expect("start the server")
+}
+
+/// The port the server chose for `APP_PORT=0`, read from its `--port-file` once it listens.
+async fn bound_port(port_file: &Path) -> u16 {
+    for _ in 0..100 {
+        if let Ok(addrs) = std::fs::read_to_string(port_file)
+            && let Some(addr) = addrs.lines().next()
+        {
+            return addr.parse::<SocketAddr>().expect("a host:port line").port();
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("server did not write {}", port_file.display());
+}
+
+async fn wait_until_up(client: &reqwest::Client, base: &str) {
+    for _ in 0..100 {
+        if client.get(format!("{base}/healthz")).send().await.is_ok() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("server did not start");
+}
+
+/// A visitor that loaded the login page: its cookies and the CSRF token they carry.
+struct Visitor {
+    cookies: String,
+    token: String,
+}
+
+impl Visitor {
+    async fn new(client: &reqwest::Client, base: &str) -> Self {
+        let res = client
+            .get(format!("{base}/login"))
+            .header(header::ACCEPT, "text/html")
+            .send()
+            .await
+            .unwrap();
+        let cookies: BTreeMap<String, String> = res
+            .headers()
+            .get_all(header::SET_COOKIE)
+            .iter()
+            .map(|set_cookie| {
+                let pair = set_cookie.to_str().unwrap().split(';').next().unwrap();
+                let (name, value) = pair.split_once('=').unwrap();
+                (name.to_owned(), value.to_owned())
+            })
+            .collect();
+        // The token is alphanumeric, so the cookie value needs no decoding.
+        let token = cookies.get("XSRF-TOKEN").expect("an XSRF-TOKEN cookie").clone();
+        let cookies =
+            cookies.iter().map(|(name, value)| format!("{name}={value}")).collect::<Vec<_>>();
+        Visitor { cookies: cookies.join("; "), token }
+    }
+
+    fn post_login(&self, client: &reqwest::Client, base: &str) -> RequestBuilder {
+        client.post(format!("{base}/login")).header(header::COOKIE, &self.cookies)
+    }
+}
+
+async fn status(request: RequestBuilder) -> u16 {
+    request.send().await.unwrap().status().as_u16()
+}
+
+/// Where an accepted `POST /login` redirects: `/` once logged in, `/login` with errors.
+async fn logged_in(request: RequestBuilder) -> bool {
+    let res = request.send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::SEE_OTHER);
+    res.headers()[header::LOCATION] == "/"
+}
+
+#[actix_web::test]
+async fn state_changing_requests_need_the_session_token() {
+    let dir = temp_dir("config");
+    write_config(&dir);
+    // Outside the config directory, whose changes trigger a reload.
+    let port_file = dir.with_extension("port");
+    let _server = start_server(&dir, &port_file);
+    let base = format!("http://127.0.0.1:{}", bound_port(&port_file).await);
+    let client = reqwest::Client::builder()
+        .redirect(redirect::Policy::none())
+        .pool_max_idle_per_host(0)
+        .build()
+        .unwrap();
+    wait_until_up(&client, &base).await;
+    let credentials = [("username", "alice"), ("password", "pw")];
+    let json = serde_json::json!({ "username": "alice", "password": "pw" });
+
+    let visitor = Visitor::new(&client, &base).await;
+    let missing = visitor.post_login(&client, &base).json(&json);
+    assert_eq!(status(missing).await, PAGE_EXPIRED, "no token");
+
+    let mismatched =
+        visitor.post_login(&client, &base).header("X-XSRF-TOKEN", "not-the-token").json(&json);
+    assert_eq!(status(mismatched).await, PAGE_EXPIRED, "another token");
+
+    let other_session = Visitor::new(&client, &base).await;
+    let foreign = visitor.post_login(&client, &base).header("X-XSRF-TOKEN", &other_session.token);
+    assert_eq!(status(foreign.json(&json)).await, PAGE_EXPIRED, "another session's token");
+
+    // Arbitrary credentials a browser could attach to a cross-site form post.
+    let basic = visitor
+        .post_login(&client, &base)
+        .header(header::AUTHORIZATION, "Basic Zm9vOmJhcg==")
+        .form(&credentials);
+    assert_eq!(status(basic).await, PAGE_EXPIRED, "an Authorization header is no token");
+
+    let header = visitor.post_login(&client, &base).header("X-XSRF-TOKEN", &visitor.token);
+    assert!(logged_in(header.json(&json)).await, "header");
+
+    let visitor = Visitor::new(&client, &base).await;
+    let mut form = credentials.to_vec();
+    form.push(("_token", &visitor.token));
+    let field = visitor.post_login(&client, &base).form(&form);
+    assert!(logged_in(field).await, "form field");
+
+    // Bearer-token admin routes carry no cookies and no CSRF token.
+    let admin =
+        client.delete(format!("{base}/admin/users/nobody/sessions")).bearer_auth(ADMIN_TOKEN);
+    assert_eq!(status(admin).await, StatusCode::OK.as_u16(), "admin route");
+
+    let _ = std::fs::remove_dir_all(&dir);
+    let _ = std::fs::remove_file(&port_file);
+}
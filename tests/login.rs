@@ -0,0 +1,185 @@
+//! `POST /login` input handling, against the server binary.
+//!
+//! Bodies that fail validation, including ones that do not parse at all, redirect back to the
+//! form with Inertia validation errors, which the next page load shows; wrong credentials redirect
+//! to `/login` with an error on `username`.
+
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+
+use argon2::{Argon2, PasswordHasher as _, password_hash::SaltString};
+use reqwest::{StatusCode, header, redirect};
+use tokio::process::{Child, Command};
+
+const USERNAME_ERROR: &str = "Please enter your username.";
+const PASSWORD_ERROR: &str = "Please enter your password.";
+const CREDENTIALS_ERROR: &str = "These credentials do not match our records.";
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("login-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Account `alice` with password `pw`.
+fn write_config(config_dir: &Path) {
+    let salt = SaltString::encode_b64(b"login-test-salt").unwrap();
+    let hash = Argon2::default().hash_password(b"pw", &salt).unwrap().to_string();
+    let config = format!("[[users.accounts]]\nusername = \"alice\"\npassword_hash = \"{hash}\"\n");
+    std::fs::write(config_dir.join("local.toml"), config).unwrap();
+}
+
+fn start_server(config_dir: &Path, port_file: &Path) -> Child {
+    Command::new(env!("CARGO_BIN_EXE_hello-actix"))
+        .args(["serve", "--port-file"])
+        .arg(port_file)
+        .env_clear()
+        .env("PATH", std::env::var_os("PATH").unwrap_or_default())
+        .env("APP_CONFIG_DIR", config_dir)
+        .env("APP_MODE", "test")
+        .env("APP_PORT", "0")
+        .env("APP_SSR__ENABLED", "off")
+        .env("APP_SESSION__STORE", "memory")
+        .env("APP_METRICS__ENABLED", "false")
+        .env("APP_ACCESS_LOG__ENABLED", "false")
+        .env("APP_LOG_LEVEL", "error")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .expect("start the server")
+}
+
+/// The port the server chose for `APP_PORT=0`, read from its `--port-file` once it listens.
+async fn bound_port(port_file: &Path) -> u16 {
+    for _ in 0..100 {
+        if let Ok(addrs) = std::fs::read_to_string(port_file)
+            && let Some(addr) = addrs.lines().next()
+        {
+            return addr.parse::<SocketAddr>().expect("a host:port line").port();
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("server did not write {}", port_file.display());
+}
+
+async fn wait_until_up(client: &reqwest::Client, base: &str) {
+    for _ in 0..100 {
+        if client.get(format!("{base}/healthz")).send().await.is_ok() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("server did not start");
+}
+
+/// A visitor on the login page, keeping its cookies between requests.
+struct Visitor {
+    client: reqwest::Client,
+    base: String,
+    cookies: BTreeMap<String, String>,
+}
+
+impl Visitor {
+    async fn new(client: &reqwest::Client, base: &str) -> Self {
+        let mut visitor =
+            Visitor { client: client.clone(), base: base.to_owned(), cookies: BTreeMap::new() };
+        visitor.login_page().await;
+        visitor
+    }
+
+    fn keep_cookies(&mut self, res: &reqwest::Response) {
+        for set_cookie in res.headers().get_all(header::SET_COOKIE) {
+            let pair = set_cookie.to_str().unwrap().split(';').next().unwrap();
+            let (name, value) = pair.split_once('=').unwrap();
+            self.cookies.insert(name.to_owned(), value.to_owned());
+        }
+    }
+
+    fn cookie_header(&self) -> String {
+        let pairs = self.cookies.iter().map(|(name, value)| format!("{name}={value}"));
+        pairs.collect::<Vec<_>>().join("; ")
+    }
+
+    /// `GET /login` as a page load; returns the HTML.
+    async fn login_page(&mut self) -> String {
+        let res = self
+            .client
+            .get(format!("{}/login", self.base))
+            .header(header::ACCEPT, "text/html")
+            .header(header::COOKIE, self.cookie_header())
+            .send()
+            .await
+            .unwrap();
+        self.keep_cookies(&res);
+        res.text().await.unwrap()
+    }
+
+    /// `POST /login` from the login page with `body` as `content_type`; returns the status and
+    /// the redirect target.
+    async fn submit(&mut self, content_type: &str, body: &str) -> (StatusCode, String) {
+        // The token is alphanumeric, so the cookie value needs no decoding.
+        let token = self.cookies.get("XSRF-TOKEN").expect("an XSRF-TOKEN cookie").clone();
+        let res = self
+            .client
+            .post(format!("{}/login", self.base))
+            .header(header::COOKIE, self.cookie_header())
+            .header(header::REFERER, format!("{}/login", self.base))
+            .header(header::CONTENT_TYPE, content_type)
+            .header("X-XSRF-TOKEN", token)
+            .body(body.to_owned())
+            .send()
+            .await
+            .unwrap();
+        self.keep_cookies(&res);
+        let location = res.headers().get(header::LOCATION).map(|l| l.to_str().unwrap().to_owned());
+        (res.status(), location.unwrap_or_default())
+    }
+}
+
+#[actix_web::test]
+async fn invalid_logins_redirect_back_with_errors() {
+    let dir = temp_dir("config");
+    write_config(&dir);
+    // Outside the config directory, whose changes trigger a reload.
+    let port_file = dir.with_extension("port");
+    let _server = start_server(&dir, &port_file);
+    let base = format!("http://127.0.0.1:{}", bound_port(&port_file).await);
+    let client = reqwest::Client::builder()
+        .redirect(redirect::Policy::none())
+        .pool_max_idle_per_host(0)
+        .build()
+        .unwrap();
+    wait_until_up(&client, &base).await;
+    let back = format!("{base}/login");
+
+    let mut visitor = Visitor::new(&client, &base).await;
+    let malformed = visitor.submit("application/json", "{\"username\": ").await;
+    assert_eq!(malformed, (StatusCode::FOUND, back.clone()), "malformed JSON");
+    let page = visitor.login_page().await;
+    assert!(page.contains(USERNAME_ERROR) && page.contains(PASSWORD_ERROR), "{page}");
+
+    let missing = visitor.submit("application/json", r#"{"username": "alice"}"#).await;
+    assert_eq!(missing, (StatusCode::FOUND, back.clone()), "missing password");
+    let page = visitor.login_page().await;
+    assert!(page.contains(PASSWORD_ERROR) && !page.contains(USERNAME_ERROR), "{page}");
+
+    let unsupported = visitor.submit("text/plain", "alice:pw").await;
+    assert_eq!(unsupported, (StatusCode::FOUND, back.clone()), "neither JSON nor a form");
+    assert!(visitor.login_page().await.contains(USERNAME_ERROR));
+
+    let wrong = visitor.submit("application/x-www-form-urlencoded", "username=alice&password=x");
+    assert_eq!(wrong.await, (StatusCode::SEE_OTHER, "/login".to_owned()), "wrong password");
+    assert!(visitor.login_page().await.contains(CREDENTIALS_ERROR));
+
+    let right = visitor.submit("application/x-www-form-urlencoded", "username=alice&password=pw");
+    assert_eq!(right.await, (StatusCode::SEE_OTHER, "/".to_owned()));
+
+    let _ = std::fs::remove_dir_all(&dir);
+    let _ = std::fs::remove_file(&port_file);
+}
@@ -0,0 +1,194 @@
+//! OTLP trace export against an in-process receiver standing in for a collector.
+//!
+//! Runs the server binary with `telemetry.otlp.endpoint` pointing at a local `POST /v1/traces`
+//! handler that decodes the protobuf payload, sends requests carrying a W3C `traceparent`, then
+//! stops the server with SIGTERM (which flushes the last batch) and inspects the spans it got.
+
+use std::{
+    net::SocketAddr,
+    path::Path,
+    process::Stdio,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use actix_web::{App, HttpResponse, HttpServer, web};
+use opentelemetry_proto::tonic::{
+    collector::trace::v1::ExportTraceServiceRequest,
+    common::v1::{AnyValue, any_value},
+    trace::v1::{Span, span::SpanKind},
+};
+use prost::Message as _;
+use tokio::process::{Child, Command};
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+const PARENT_ID: &str = "00f067aa0ba902b7";
+
+/// Spans received by the stand-in collector, with the `service.name` of their resource.
+type Received = Arc<Mutex<Vec<(String, Span)>>>;
+
+/// Start the stand-in collector and return its traces URL.
+fn start_receiver(received: Received) -> String {
+    let server = HttpServer::new(move || {
+        App::new().app_data(web::Data::new(received.clone())).route(
+            "/v1/traces",
+            web::post().to(|body: web::Bytes, received: web::Data<Received>| async move {
+                let Ok(request) = ExportTraceServiceRequest::decode(body) else {
+                    return HttpResponse::BadRequest().finish();
+                };
+                let mut received = received.lock().unwrap();
+                for resource_spans in request.resource_spans {
+                    let service = resource_spans
+                        .resource
+                        .iter()
+                        .flat_map(|resource| &resource.attributes)
+                        .find(|kv| kv.key == "service.name")
+                        .and_then(|kv| string_value(kv.value.as_ref()))
+                        .unwrap_or_default();
+                    for span in resource_spans.scope_spans.into_iter().flat_map(|s| s.spans) {
+                        received.push((service.clone(), span));
+                    }
+                }
+                HttpResponse::Ok().content_type("application/x-protobuf").finish()
+            }),
+        )
+    })
+    .workers(1)
+    .bind(("127.0.0.1", 0))
+    .expect("bind the receiver");
+    let addr = server.addrs()[0];
+    actix_web::rt::spawn(server.run());
+    format!("http://{addr}/v1/traces")
+}
+
+fn string_value(value: Option<&AnyValue>) -> Option<String> {
+    match value?.value.as_ref()? {
+        any_value::Value::StringValue(s) => Some(s.clone()),
+        _ => None,
+    }
+}
+
+fn start_server(endpoint: &str, config_dir: &Path, port_file: &Path) -> Child {
+    Command::new(env!("CARGO_BIN_EXE_hello-actix"))
+        .args(["serve", "--port-file"])
+        .arg(port_file)
+        .env_clear()
+        .env("PATH", std::env::var_os("PATH").unwrap_or_default())
+        .env("APP_CONFIG_DIR", config_dir)
+        .env("APP_MODE", "test")
+        .env("APP_PORT", "0")
+        .env("APP_SSR__ENABLED", "off")
+        .env("APP_SESSION__STORE", "memory")
+        .env("APP_METRICS__ENABLED", "false")
+        .env("APP_LOG_LEVEL", "error")
+        .env("APP_TELEMETRY__OTLP__ENDPOINT", endpoint)
+        .env("APP_TELEMETRY__OTLP__SERVICE_NAME", "otlp-test")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .expect("start the server")
+}
+
+/// The port the server chose for `APP_PORT=0`, read from its `--port-file` once it listens.
+async fn bound_port(port_file: &Path) -> u16 {
+    for _ in 0..100 {
+        if let Ok(addrs) = std::fs::read_to_string(port_file)
+            && let Some(addr) = addrs.lines().next()
+        {
+            return addr.parse::<SocketAddr>().expect("a host:port line").port();
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("server did not write {}", port_file.display());
+}
+
+async fn wait_until_up(client: &reqwest::Client, base: &str) {
+    for _ in 0..100 {
+        if client.get(format!("{base}/healthz")).send().await.is_ok() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("server did not start");
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[actix_web::test]
+async fn exports_request_and_session_spans_with_propagated_context() {
+    let received = Received::default();
+    let endpoint = start_receiver(received.clone());
+    let config_dir = std::env::temp_dir().join(format!("otlp-test-{}", std::process::id()));
+    std::fs::create_dir_all(&config_dir).unwrap();
+    // Outside the config directory, whose changes trigger a reload.
+    let port_file = config_dir.with_extension("port");
+    let mut server = start_server(&endpoint, &config_dir, &port_file);
+    let port = bound_port(&port_file).await;
+    let base = format!("http://127.0.0.1:{port}");
+    let client = reqwest::Client::new();
+    wait_until_up(&client, &base).await;
+
+    let res = client
+        .get(format!("{base}/hey"))
+        .header("traceparent", format!("00-{TRACE_ID}-{PARENT_ID}-01"))
+        .send()
+        .await
+        .unwrap();
+    assert!(res.status().is_success());
+    let traceparent = res.headers()["traceparent"].to_str().unwrap().to_owned();
+    assert!(
+        traceparent.starts_with(&format!("00-{TRACE_ID}-")) && traceparent.ends_with("-01"),
+        "the response continues the caller's trace: {traceparent}"
+    );
+    assert!(!traceparent.contains(PARENT_ID), "the response names the server span");
+
+    // A page load writes the session (CSRF token), so the memory store is traced.
+    let res =
+        client.get(format!("{base}/login")).header("accept", "text/html").send().await.unwrap();
+    let login_trace = res.headers()["traceparent"].to_str().unwrap()[3..35].to_owned();
+
+    let pid = server.id().unwrap().to_string();
+    let status = Command::new("kill").args(["-TERM", &pid]).status().await.unwrap();
+    assert!(status.success());
+    tokio::time::timeout(Duration::from_secs(30), server.wait())
+        .await
+        .expect("server stops after SIGTERM")
+        .unwrap();
+    let _ = std::fs::remove_dir_all(&config_dir);
+    let _ = std::fs::remove_file(&port_file);
+
+    let spans = received.lock().unwrap();
+    assert!(spans.iter().all(|(service, _)| service == "otlp-test"));
+
+    let hey = spans
+        .iter()
+        .map(|(_, span)| span)
+        .find(|span| span.name == "GET /hey")
+        .unwrap_or_else(|| panic!("no GET /hey span in {:?}", names(&spans)));
+    assert_eq!(hex(&hey.trace_id), TRACE_ID);
+    assert_eq!(hex(&hey.parent_span_id), PARENT_ID);
+    assert_eq!(hey.kind, SpanKind::Server as i32);
+    assert_eq!(format!("00-{TRACE_ID}-{}-01", hex(&hey.span_id)), traceparent);
+
+    let login = spans
+        .iter()
+        .map(|(_, span)| span)
+        .find(|span| span.name == "GET /login")
+        .unwrap_or_else(|| panic!("no GET /login span in {:?}", names(&spans)));
+    assert_eq!(hex(&login.trace_id), login_trace);
+    assert!(login.parent_span_id.is_empty(), "a request without traceparent starts a trace");
+    assert!(
+        spans.iter().any(|(_, span)| span.name.starts_with("session ")
+            && span.trace_id == login.trace_id
+            && span.kind == SpanKind::Client as i32),
+        "no session store span in the GET /login trace: {:?}",
+        names(&spans)
+    );
+}
+
+fn names(spans: &[(String, Span)]) -> Vec<&str> {
+    spans.iter().map(|(_, span)| span.name.as_str()).collect()
+}
@@ -0,0 +1,318 @@
+//! Live session behaviour checked against `specs/SessionModel.tla`.
+//!
+//! Runs the server binary with `model_trace` set, drives logins, protected requests, a logout, an
+//! admin revocation and replays of the dead cookies, stops it, then replays the recorded trace with
+//! `hello-actix check-trace`. A change that lets a revoked cookie through or reuses a session ID
+//! breaks `TypeOK`, `SessionIntegrity` or an action's guard somewhere in the trace, and fails here.
+
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    process::{Output, Stdio},
+    time::Duration,
+};
+
+use argon2::{Argon2, PasswordHasher as _, password_hash::SaltString};
+use reqwest::{StatusCode, header, redirect};
+use serde_json::{Value, json};
+use tokio::process::{Child, Command};
+
+const ADMIN_TOKEN: &str = "session-model-admin";
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("session-model-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Accounts `alice` and `bob`, both with password `pw`, and an admin token.
+fn write_config(config_dir: &Path) {
+    let salt = SaltString::encode_b64(b"session-model").unwrap();
+    let hash = Argon2::default().hash_password(b"pw", &salt).unwrap().to_string();
+    let accounts: String = ["alice", "bob"]
+        .iter()
+        .map(|user| {
+            format!("[[users.accounts]]\nusername = \"{user}\"\npassword_hash = \"{hash}\"\n\n")
+        })
+        .collect();
+    let config = format!("admin_token = \"{ADMIN_TOKEN}\"\n\n{accounts}");
+    std::fs::write(config_dir.join("local.toml"), config).unwrap();
+}
+
+fn start_server(config_dir: &Path, trace: &Path, port_file: &Path) -> Child {
+    Command::new(env!("CARGO_BIN_EXE_hello-actix"))
+        .args(["serve", "--port-file"])
+        .arg(port_file)
+        .env_clear()
+        .env("PATH", std::env::var_os("PATH").unwrap_or_default())
+        .env("APP_CONFIG_DIR", config_dir)
+        .env("APP_MODE", "test")
+        .env("APP_PORT", "0")
+        .env("APP_SSR__ENABLED", "off")
+        .env("APP_SESSION__STORE", "memory")
+        .env("APP_METRICS__ENABLED", "false")
+        .env("APP_ACCESS_LOG__ENABLED", "false")
+        .env("APP_LOG_LEVEL", "error")
+        .env("APP_MODEL_TRACE", trace)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .expect("start the server")
+}
+
+/// The port the server chose for `APP_PORT=0`, read from its `--port-file` once it listens.
+async fn bound_port(port_file: &Path) -> u16 {
+    for _ in 0..100 {
+        if let Ok(addrs) = std::fs::read_to_string(port_file)
+            && let Some(addr) = addrs.lines().next()
+        {
+            return addr.parse::<SocketAddr>().expect("a host:port line").port();
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("server did not write {}", port_file.display());
+}
+
+async fn wait_until_up(client: &reqwest::Client, base: &str) {
+    for _ in 0..100 {
+        if client.get(format!("{base}/healthz")).send().await.is_ok() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("server did not start");
+}
+
+async fn check_trace(trace: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_hello-actix"))
+        .arg("check-trace")
+        .arg(trace)
+        .output()
+        .await
+        .expect("run check-trace")
+}
+
+/// A client with its own cookies, sending the CSRF token the way the frontend does.
+#[derive(Clone)]
+struct Browser {
+    client: reqwest::Client,
+    base: String,
+    cookies: BTreeMap<String, String>,
+}
+
+impl Browser {
+    fn new(client: &reqwest::Client, base: &str) -> Self {
+        Browser { client: client.clone(), base: base.to_owned(), cookies: BTreeMap::new() }
+    }
+
+    fn keep_cookies(&mut self, res: &reqwest::Response) {
+        for set_cookie in res.headers().get_all(header::SET_COOKIE) {
+            let set_cookie = set_cookie.to_str().unwrap();
+            let (pair, attributes) = set_cookie.split_once(';').unwrap_or((set_cookie, ""));
+            let (name, value) = pair.split_once('=').unwrap();
+            if attributes.to_ascii_lowercase().contains("max-age=0") {
+                self.cookies.remove(name);
+            } else {
+                self.cookies.insert(name.to_owned(), value.to_owned());
+            }
+        }
+    }
+
+    fn cookie_header(&self) -> String {
+        self.cookies
+            .iter()
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+
+    /// The `XSRF-TOKEN` cookie, percent-decoded.
+    fn xsrf_token(&self) -> String {
+        let raw = self.cookies.get("XSRF-TOKEN").expect("an XSRF-TOKEN cookie").as_bytes();
+        let mut token = Vec::with_capacity(raw.len());
+        let mut i = 0;
+        while i < raw.len() {
+            if raw[i] == b'%' {
+                let hex = std::str::from_utf8(&raw[i + 1..i + 3]).unwrap();
+                token.push(u8::from_str_radix(hex, 16).unwrap());
+                i += 3;
+            } else {
+                token.push(raw[i]);
+                i += 1;
+            }
+        }
+        String::from_utf8(token).unwrap()
+    }
+
+    /// `GET path` as a page load; returns the HTML.
+    async fn get(&mut self, path: &str) -> String {
+        let res = self
+            .client
+            .get(format!("{}{path}", self.base))
+            .header(header::ACCEPT, "text/html")
+            .header(header::COOKIE, self.cookie_header())
+            .send()
+            .await
+            .unwrap();
+        self.keep_cookies(&res);
+        res.text().await.unwrap()
+    }
+
+    async fn post(&mut self, path: &str, body: Value) -> StatusCode {
+        let res = self
+            .client
+            .post(format!("{}{path}", self.base))
+            .header(header::COOKIE, self.cookie_header())
+            .header("X-XSRF-TOKEN", self.xsrf_token())
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+        self.keep_cookies(&res);
+        res.status()
+    }
+
+    async fn login(&mut self, user: &str) {
+        self.get("/login").await;
+        let status = self.post("/login", json!({ "username": user, "password": "pw" })).await;
+        assert_eq!(status, StatusCode::SEE_OTHER, "login as {user}");
+    }
+
+    async fn logout(&mut self) {
+        assert_eq!(self.post("/logout", json!({})).await, StatusCode::SEE_OTHER);
+    }
+
+    /// Whether the home page shows `user` as logged in (the shared `auth` prop).
+    async fn signed_in_as(&mut self, user: &str) -> bool {
+        self.get("/").await.contains(&format!(r#""auth":{{"user":{{"id":"{user}""#))
+    }
+}
+
+#[actix_web::test]
+async fn live_session_flows_follow_the_model() {
+    let dir = temp_dir("live");
+    write_config(&dir);
+    let trace = dir.join("trace.ndjson");
+    // Outside the config directory, whose changes trigger a reload.
+    let port_file = dir.with_extension("port");
+    let mut server = start_server(&dir, &trace, &port_file);
+    let port = bound_port(&port_file).await;
+    let base = format!("http://127.0.0.1:{port}");
+    // Fresh connections: the server closes one after a POST whose body it did not read, and a
+    // pooled client could send the next request on it.
+    let client = reqwest::Client::builder()
+        .redirect(redirect::Policy::none())
+        .pool_max_idle_per_host(0)
+        .build()
+        .unwrap();
+    wait_until_up(&client, &base).await;
+
+    let mut alice = Browser::new(&client, &base);
+    alice.login("alice").await;
+    assert!(alice.signed_in_as("alice").await);
+    let mut bob = Browser::new(&client, &base);
+    bob.login("bob").await;
+    assert!(bob.signed_in_as("bob").await);
+
+    // A copy of alice's cookies outlives her logout: the model lets clients keep them. The
+    // outcome of the replays is asserted after the trace check, whose report is more telling.
+    let mut replay = alice.clone();
+    alice.logout().await;
+    let replay_after_logout = replay.signed_in_as("alice").await;
+
+    let res = client
+        .delete(format!("{base}/admin/users/bob/sessions"))
+        .bearer_auth(ADMIN_TOKEN)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.json::<Value>().await.unwrap(), json!({ "revoked": 1 }));
+    let replay_after_revocation = bob.signed_in_as("bob").await;
+
+    alice.login("alice").await;
+    assert!(alice.signed_in_as("alice").await);
+
+    let pid = server.id().unwrap().to_string();
+    let status = Command::new("kill").args(["-TERM", &pid]).status().await.unwrap();
+    assert!(status.success());
+    tokio::time::timeout(Duration::from_secs(30), server.wait())
+        .await
+        .expect("server stops after SIGTERM")
+        .unwrap();
+
+    let output = check_trace(&trace).await;
+    let text = std::fs::read_to_string(&trace).unwrap();
+    assert!(output.status.success(), "{}\n{text}", String::from_utf8_lossy(&output.stderr));
+    assert!(!replay_after_logout, "a cookie replayed after logout is refused");
+    assert!(!replay_after_revocation, "a cookie revoked by an admin is refused");
+
+    let steps: Vec<(String, String)> = text
+        .lines()
+        .map(|line| serde_json::from_str::<Value>(line).unwrap())
+        .map(|step| {
+            let field = |key: &str| step[key].as_str().unwrap_or_default().to_owned();
+            (field("action"), field("user"))
+        })
+        .collect();
+    let expected = [
+        ("Init", ""),
+        ("Login", "alice"),
+        ("AccessProtected", "alice"),
+        ("Login", "bob"),
+        ("AccessProtected", "bob"),
+        ("AccessProtected", "alice"),
+        ("Logout", "alice"),
+        ("ExpireSession", ""),
+        ("Login", "alice"),
+        ("AccessProtected", "alice"),
+    ];
+    let expected: Vec<(String, String)> =
+        expected.iter().map(|(action, user)| (action.to_string(), user.to_string())).collect();
+    assert_eq!(steps, expected, "{text}");
+    let _ = std::fs::remove_dir_all(&dir);
+    let _ = std::fs::remove_file(&port_file);
+}
+
+#[actix_web::test]
+async fn check_trace_rejects_steps_that_break_the_model() {
+    let dir = temp_dir("broken");
+    let s = "0123456789abcdef0123456789abcdef";
+    let init = json!({ "step": 0, "action": "Init",
+        "state": { "serverSessions": {}, "clientCookies": [] } });
+    let login = json!({ "step": 1, "action": "Login", "user": "alice", "session": s,
+        "state": { "serverSessions": { s: "alice" },
+            "clientCookies": [{ "u": "alice", "s": s }] } });
+    let logout = json!({ "step": 2, "action": "Logout", "user": "alice", "session": s,
+        "state": { "serverSessions": { s: "NULL" },
+            "clientCookies": [{ "u": "alice", "s": s }] } });
+
+    let cases = [
+        // The server granted a request with a session it had revoked.
+        (
+            json!({ "step": 3, "action": "AccessProtected", "user": "alice", "session": s,
+                "state": logout["state"] }),
+            "AccessProtected(alice) with session 0123456789abcdef0123456789abcdef owned by NULL",
+        ),
+        // A login handed out an ID that alice's cookie still carries.
+        (
+            json!({ "step": 3, "action": "Login", "user": "bob", "session": s,
+                "state": { "serverSessions": { s: "bob" },
+                    "clientCookies": [{ "u": "alice", "s": s }, { "u": "bob", "s": s }] } }),
+            "SessionIntegrity",
+        ),
+    ];
+    for (index, (step, violation)) in cases.into_iter().enumerate() {
+        let trace = dir.join(format!("trace-{index}.ndjson"));
+        let lines: Vec<String> =
+            [&init, &login, &logout, &step].iter().map(|v| v.to_string()).collect();
+        std::fs::write(&trace, lines.join("\n")).unwrap();
+
+        let output = check_trace(&trace).await;
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(output.status.code(), Some(1), "{stderr}");
+        assert!(stderr.contains("line 4") && stderr.contains(violation), "{stderr}");
+    }
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -1,13 +1,22 @@
-import { Head, Link } from "@inertiajs/react";
+import { FormEvent } from "react";
+import { Head, Link, useForm } from "@inertiajs/react";
 
 type Props = {
   user: {
     name: string;
     email: string;
   };
+  flash?: { success?: string; error?: string };
 };
 
-export default function Contact({ user }: Props) {
+export default function Contact({ user, flash }: Props) {
+  const form = useForm({ name: "", email: "", message: "" });
+
+  const submit = (e: FormEvent) => {
+    e.preventDefault();
+    form.post("/contact", { onSuccess: () => form.reset() });
+  };
+
   return (
     <>
       <Head>
@@ -49,6 +58,56 @@ export default function Contact({ user }: Props) {
             </div>
           </div>
 
+          {flash?.success && (
+            <div className="rounded-xl bg-emerald-600/20 border border-emerald-400/30 p-4">
+              {flash.success}
+            </div>
+          )}
+
+          <form onSubmit={submit} className="flex flex-col gap-3">
+            {(["name", "email"] as const).map((field) => (
+              <label key={field} className="flex flex-col gap-1">
+                <span className="text-sm uppercase tracking-wide text-white/60">{field}</span>
+                <input
+                  type={field === "email" ? "email" : "text"}
+                  name={field}
+                  value={form.data[field]}
+                  onChange={(e) => form.setData(field, e.target.value)}
+                  className="px-4 py-2 rounded-lg bg-white/10 border border-white/20"
+                />
+                {form.errors[field] && (
+                  <span className="text-sm text-red-300">{form.errors[field]}</span>
+                )}
+              </label>
+            ))}
+
+            <label className="flex flex-col gap-1">
+              <span className="text-sm uppercase tracking-wide text-white/60">Message</span>
+              <textarea
+                name="message"
+                rows={4}
+                value={form.data.message}
+                onChange={(e) => form.setData("message", e.target.value)}
+                className="px-4 py-2 rounded-lg bg-white/10 border border-white/20"
+              />
+              {form.errors.message && (
+                <span className="text-sm text-red-300">{form.errors.message}</span>
+              )}
+            </label>
+
+            <button
+              type="submit"
+              disabled={form.processing}
+              className="
+                self-start px-5 py-3 rounded-lg
+                bg-purple-700 hover:bg-purple-800 active:bg-purple-900
+                transition font-semibold disabled:opacity-60
+              "
+            >
+              Send message
+            </button>
+          </form>
+
           <footer className="flex flex-wrap gap-3 justify-center pt-2">
             <Link
               href="/"
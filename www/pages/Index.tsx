@@ -1,7 +1,12 @@
 import { Head, Link } from "@inertiajs/react";
 import { useState } from "react";
 
-type AuthUser = { id: string } | null;
+type AuthUser = {
+  id: string;
+  name: string;
+  roles: string[];
+  session: { loggedInAt: number; expiresAt: number };
+} | null;
 
 type Props = {
   version: string;
@@ -32,7 +37,7 @@ export default function Index({ message, version, auth }: Props) {
               <h2 className="text-2xl font-black">Auth state</h2>
               {user ? (
                 <p className="text-white/80 mt-1">
-                  Logged in as <span className="font-semibold">{user.id}</span>
+                  Logged in as <span className="font-semibold">{user.name}</span>
                 </p>
               ) : (
                 <p className="text-white/80 mt-1">You are not logged in.</p>
@@ -42,11 +47,9 @@ export default function Index({ message, version, auth }: Props) {
             <div className="flex flex-wrap gap-3">
               {!user ? (
                 <>
-                  {/* Post to /login so the server attaches identity and redirects */}
+                  {/* Go to the login form; it posts credentials to /login */}
                   <Link
                     href="/login"
-                    method="post"
-                    as="button"
                     className="
                       px-5 py-2 rounded-lg
                       bg-purple-700 hover:bg-purple-800 active:bg-purple-900
@@ -1,11 +1,23 @@
-import { Head, Link, usePage } from "@inertiajs/react";
+import { FormEvent } from "react";
+import { Head, Link, useForm, usePage } from "@inertiajs/react";
 
-type AuthUser = { id: string } | null;
+type AuthUser = {
+  id: string;
+  name: string;
+  roles: string[];
+  session: { loggedInAt: number; expiresAt: number };
+} | null;
 type PageProps = { auth?: { user: AuthUser } };
 
 export default function Login() {
   const { auth } = usePage<PageProps>().props;
   const user = auth?.user ?? null;
+  const form = useForm({ username: "", password: "" });
+
+  const submit = (e: FormEvent) => {
+    e.preventDefault();
+    form.post("/login", { onFinish: () => form.reset("password") });
+  };
 
   return (
     <>
@@ -28,7 +40,7 @@ export default function Login() {
               <div className="rounded-xl bg-emerald-600/20 border border-emerald-400/30 p-4">
                 <p className="text-lg">
                   You are logged in as
-                  <span className="font-semibold"> {user.id}</span>.
+                  <span className="font-semibold"> {user.name}</span>.
                 </p>
               </div>
 
@@ -60,27 +72,58 @@ export default function Login() {
                 <p className="text-lg">You are currently not logged in.</p>
               </div>
 
-              <div className="flex gap-3 flex-wrap">
-                <Link
-                  href="/login"
-                  method="post"
-                  as="button"
-                  className="
-                    px-5 py-3 rounded-lg
-                    bg-purple-700 hover:bg-purple-800 active:bg-purple-900
-                    transition font-semibold
-                  "
-                >
-                  Log in
-                </Link>
+              <form onSubmit={submit} className="flex flex-col gap-3">
+                <label className="flex flex-col gap-1">
+                  <span className="text-sm text-white/80">Username</span>
+                  <input
+                    type="text"
+                    name="username"
+                    autoComplete="username"
+                    value={form.data.username}
+                    onChange={(e) => form.setData("username", e.target.value)}
+                    className="px-4 py-2 rounded-lg bg-white/10 border border-white/20"
+                  />
+                  {form.errors.username && (
+                    <span className="text-sm text-red-300">{form.errors.username}</span>
+                  )}
+                </label>
 
-                <Link
-                  href="/"
-                  className="px-5 py-3 rounded-lg bg-white/10 hover:bg-white/15 transition font-medium"
-                >
-                  Back to Home
-                </Link>
-              </div>
+                <label className="flex flex-col gap-1">
+                  <span className="text-sm text-white/80">Password</span>
+                  <input
+                    type="password"
+                    name="password"
+                    autoComplete="current-password"
+                    value={form.data.password}
+                    onChange={(e) => form.setData("password", e.target.value)}
+                    className="px-4 py-2 rounded-lg bg-white/10 border border-white/20"
+                  />
+                  {form.errors.password && (
+                    <span className="text-sm text-red-300">{form.errors.password}</span>
+                  )}
+                </label>
+
+                <div className="flex gap-3 flex-wrap">
+                  <button
+                    type="submit"
+                    disabled={form.processing}
+                    className="
+                      px-5 py-3 rounded-lg
+                      bg-purple-700 hover:bg-purple-800 active:bg-purple-900
+                      transition font-semibold disabled:opacity-60
+                    "
+                  >
+                    Log in
+                  </button>
+
+                  <Link
+                    href="/"
+                    className="px-5 py-3 rounded-lg bg-white/10 hover:bg-white/15 transition font-medium"
+                  >
+                    Back to Home
+                  </Link>
+                </div>
+              </form>
             </section>
           )}
 
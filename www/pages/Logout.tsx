@@ -1,7 +1,9 @@
-import { Head, router } from "@inertiajs/react";
+import { Head, router, usePage } from "@inertiajs/react";
 import { useEffect } from "react";
 
 export default function Logout() {
+  const { csrfToken } = usePage<{ csrfToken?: string }>().props;
+
   useEffect(() => {
     // Immediately post to /logout on mount; the server responds with a redirect.
     router.post("/logout", {}, { preserveScroll: true });
@@ -16,6 +18,7 @@ export default function Logout() {
 
       <main className="w-full h-full flex items-center justify-center px-6">
         <form method="post" action="/logout" className="w-full max-w-md text-center">
+          <input type="hidden" name="_token" value={csrfToken ?? ""} />
           <p className="text-xl font-medium mb-4">Logging you out...</p>
           <p className="text-white/70">
             If you are not redirected automatically,
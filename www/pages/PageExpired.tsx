@@ -0,0 +1,27 @@
+import { Head, Link } from "@inertiajs/react";
+
+export default function PageExpired() {
+  return (
+    <>
+      <Head>
+        <title>Page Expired</title>
+        <meta name="robots" content="noindex" />
+      </Head>
+
+      <main className="w-full h-full flex items-center justify-center px-6">
+        <section className="w-full max-w-md rounded-2xl bg-white/10 p-8 text-center space-y-4">
+          <h1 className="text-4xl font-black">419</h1>
+          <p className="text-white/80">
+            This page has expired. Please reload it and try again.
+          </p>
+          <Link
+            href="/"
+            className="inline-block px-5 py-3 rounded-lg bg-white/10 hover:bg-white/15 transition font-medium"
+          >
+            Back to Home
+          </Link>
+        </section>
+      </main>
+    </>
+  );
+}
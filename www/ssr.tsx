@@ -10,7 +10,27 @@ export const appName = "Inertia Test";
 export const titleResolver = (title: string) =>
   title ? `${appName} - ${title}` : title;
 
-createServer((page: Page) => {
+// The backend sends its request ID (and, when it exports traces, the W3C trace context) in the
+// page props, so render failures can be matched with the request in the backend's logs.
+createServer(async (page: Page) => {
+  try {
+    return await render(page);
+  } catch (error) {
+    console.error(
+      JSON.stringify({
+        level: "ERROR",
+        message: "SSR render failed",
+        request_id: page.props.requestId ?? null,
+        traceparent: page.props.traceparent ?? null,
+        component: page.component,
+        error: String(error),
+      }),
+    );
+    throw error;
+  }
+}, port);
+
+function render(page: Page) {
   return createInertiaApp({
     page,
 
@@ -27,4 +47,4 @@ createServer((page: Page) => {
       return <App {...props} />;
     },
   });
-}, port);
+}